@@ -0,0 +1,45 @@
+use std::sync::atomic::AtomicBool;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use multi_duino::hasher::{self, Solver};
+use sha1::{Digest, Sha1};
+
+static LAST_HASH: &str = "2b4b8c5f0c4b7d0cd4fd2c8f1e9a3b7a6c2d1e0f";
+static NONCE: u64 = 45_000;
+static MAX_NONCE: u64 = 65_535;
+
+// The allocating loop ducos1a used before the midstate hasher
+fn naive(lastblockhash: &str, expected: &[u8], max_nonce: u64) -> u64 {
+    for ducos1res in 0..=max_nonce {
+        let mut hasher = Sha1::new();
+        let data = format!("{}{}", lastblockhash, ducos1res);
+        hasher.update(data.as_bytes());
+        let hash_bytes = hasher.finalize();
+
+        if hash_bytes.as_slice() == expected {
+            return ducos1res;
+        }
+    }
+    0
+}
+
+fn expected() -> [u8; 20] {
+    Sha1::digest(format!("{}{}", LAST_HASH, NONCE).as_bytes()).into()
+}
+
+fn bench_ducos1(c: &mut Criterion) {
+    let expected = expected();
+    let mut group = c.benchmark_group("ducos1");
+    group.bench_function("naive", |b| {
+        b.iter(|| naive(black_box(LAST_HASH), black_box(&expected), MAX_NONCE))
+    });
+    group.bench_function("midstate", |b| {
+        let hasher = hasher::DucoS1Hasher::new(LAST_HASH, expected);
+        let cancel = AtomicBool::new(false);
+        b.iter(|| black_box(&hasher).solve(MAX_NONCE, &cancel))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_ducos1);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+use crate::hasher::{self, DigestError, DucoS1Hasher, Solver, XxhashHasher};
+use crate::protocol::Job;
+
+// A job format the pool hands out and how to solve it
+pub trait HashAlgorithm: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // First field of the job request, tells the pool which kind of job to send
+    fn job_request(&self) -> &'static str;
+
+    fn decode(&self, job: &Job) -> Result<Box<dyn Solver>, DigestError>;
+}
+
+// SHA-1 of last hash and nonce, the network's default
+pub struct DucoS1;
+
+impl HashAlgorithm for DucoS1 {
+    fn name(&self) -> &'static str {
+        "DUCO-S1"
+    }
+
+    fn job_request(&self) -> &'static str {
+        "JOB"
+    }
+
+    fn decode(&self, job: &Job) -> Result<Box<dyn Solver>, DigestError> {
+        let expected = hasher::decode_hex::<20>(&job.expected_hash)?;
+        Ok(Box::new(DucoS1Hasher::new(&job.last_hash, expected)))
+    }
+}
+
+// XXH64 of last hash and nonce, requested with JOBXX
+pub struct Xxhash;
+
+impl HashAlgorithm for Xxhash {
+    fn name(&self) -> &'static str {
+        "XXHASH"
+    }
+
+    fn job_request(&self) -> &'static str {
+        "JOBXX"
+    }
+
+    fn decode(&self, job: &Job) -> Result<Box<dyn Solver>, DigestError> {
+        let expected = u64::from_be_bytes(hasher::decode_hex::<8>(&job.expected_hash)?);
+        Ok(Box::new(XxhashHasher::new(&job.last_hash, expected)))
+    }
+}
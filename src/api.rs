@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use log::error;
+
+use crate::structs::{self, DuinoConfig};
+
+// Fetches fresh account data for the dashboard
+pub async fn refresh_accounts(state: &structs::SharedState, settings: &DuinoConfig) {
+    let mut new_accounts: HashMap<String, structs::AccountData> = HashMap::new();
+    let mut new_accounts_list: Vec<String> = Vec::new();
+
+    let mut global = structs::AccountData {
+        hashrate: 0,
+        miners: 0,
+        connected: 0,
+        current_balance: 0.0,
+        status: "Gobal".to_string(),
+        staked: 0.0,
+        estimated_balance: 0.0,
+        warnings: 0,
+        tier: String::new(),
+    };
+    for (name, account) in settings.accounts.iter() {
+        let cloned_name = name.clone();
+        if !check_user(&settings.api_url, name, &account.key).await {
+            let new_data = structs::AccountData {
+                hashrate: 0,
+                miners: 0,
+                connected: 0,
+                current_balance: 0.0,
+                status: "Not found".to_string(),
+                staked: 0.0,
+                estimated_balance: 0.0,
+                warnings: 0,
+                tier: account.requested_difficulty().to_string(),
+            };
+            new_accounts.insert(cloned_name.clone(), new_data.clone());
+            new_accounts_list.push(cloned_name.clone());
+        } else {
+            let account_data = get_user(&settings.api_url, &cloned_name).await;
+            if account_data.success {
+                let new_data = structs::AccountData {
+                    hashrate: account.miners as u64 * account.hashrate as u64,
+                    miners: account.miners as u32,
+                    connected: account_data.result.miners.len() as u32,
+                    current_balance: account_data.result.balance.balance,
+                    status: "Connected".to_string(),
+                    staked: account_data.result.balance.stake_amount,
+                    estimated_balance: account_data.result.balance.balance
+                        * get_highest_amount(account_data.result.prices).await,
+                    warnings: account_data.result.balance.warnings,
+                    tier: account.requested_difficulty().to_string(),
+                };
+                global.hashrate += new_data.hashrate;
+                global.miners += new_data.miners;
+                global.connected += new_data.connected;
+                global.current_balance += new_data.current_balance;
+                global.estimated_balance += new_data.estimated_balance;
+                global.warnings += new_data.warnings;
+                new_accounts.insert(cloned_name.clone(), new_data.clone());
+                new_accounts_list.push(cloned_name.clone());
+            }
+        }
+    }
+    new_accounts.insert("Global".to_string(), global.clone());
+    new_accounts_list.push("Global".to_string());
+    {
+        let mut unlocked_accounts = state.accounts.write().await;
+        let mut unlocked_account_list = state.account_list.write().await;
+        unlocked_accounts.clear();
+        unlocked_account_list.clear();
+        unlocked_accounts.extend(new_accounts);
+        unlocked_account_list.extend(new_accounts_list);
+    }
+}
+
+pub async fn get_highest_amount(amounts: HashMap<String, f64>) -> f64 {
+    let mut current_highest = 0.0;
+    for (_, price) in amounts {
+        if price > current_highest {
+            current_highest = price;
+        }
+    }
+    current_highest
+}
+
+pub async fn check_user(api_url: &str, name: &String, key: &String) -> bool {
+    let client = reqwest::Client::builder()
+        .user_agent("Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/113.0")
+        .build()
+        .expect("Built client");
+    let mut retries = 0;
+    loop {
+        match client
+            .get(format!("{}/mining_key?u={}&k={}", api_url, name, key))
+            .send()
+            .await
+        {
+            Ok(response) => match response.json::<structs::AccountCheck>().await {
+                Ok(account) => {
+                    return account.success;
+                }
+                Err(_) => {
+                    retries += 1;
+                    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                    error!("Error decoding account check response, retrying... Attempt #{} Is the account banned?", retries);
+                    continue;
+                }
+            },
+            Err(_) => {
+                retries += 1;
+                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                error!(
+                    "Error making account check request, retrying... Attempt #{}",
+                    retries
+                );
+                continue;
+            }
+        }
+    }
+}
+
+pub async fn get_user(api_url: &str, name: &String) -> structs::RestAccount {
+    let client = reqwest::Client::builder()
+        .user_agent("Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/113.0")
+        .build()
+        .expect("Built client");
+    let mut retries = 0;
+    loop {
+        match client
+            .get(format!("{}/v3/users/{}", api_url, name))
+            .send()
+            .await
+        {
+            Ok(response) => match response.json::<structs::RestAccount>().await {
+                Ok(account) => {
+                    return account;
+                }
+                Err(_) => {
+                    retries += 1;
+                    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                    error!("Error decoding account get response, retrying... Attempt #{} Is the account banned?", retries);
+                    continue;
+                }
+            },
+            Err(_) => {
+                retries += 1;
+                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                error!(
+                    "Error making account get request, retrying... Attempt #{}",
+                    retries
+                );
+                continue;
+            }
+        }
+    }
+}
+
+// Gives up after the given number of attempts, or never if there is none
+pub async fn get_pool(api_url: &str, attempts: Option<u32>) -> Option<structs::PoolResult> {
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    let client = reqwest::Client::builder()
+        .user_agent("Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/113.0")
+        .build()
+        .expect("Built client");
+    let mut retries = 0;
+    loop {
+        if attempts.is_some_and(|attempts| retries >= attempts) {
+            return None;
+        }
+        match client.get(format!("{}/getPool", api_url)).send().await {
+            Ok(response) => match response.json::<structs::PoolResult>().await {
+                Ok(pool) => {
+                    return Some(pool);
+                }
+                Err(_) => {
+                    retries += 1;
+                    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                    error!(
+                        "Error decoding pool response, retrying... Attempt #{}",
+                        retries
+                    );
+                    continue;
+                }
+            },
+            Err(_) => {
+                retries += 1;
+                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                error!(
+                    "Error making pool request, retrying... Attempt #{}",
+                    retries
+                );
+                continue;
+            }
+        }
+    }
+}
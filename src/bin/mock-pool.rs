@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use multi_duino::mock::pool::{self, Script};
+use tokio::net::TcpListener;
+
+// See README.md for the available options
+fn script_from_args() -> Script {
+    let mut script = Script::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .unwrap_or_else(|| panic!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--port" => script.port = parse(&arg, &value),
+            "--version" => script.version = value,
+            "--difficulty" => script.difficulty = parse(&arg, &value),
+            "--bad-every" => script.bad_every = parse(&arg, &value),
+            "--block-every" => script.block_every = parse(&arg, &value),
+            "--invalid-job-every" => script.invalid_job_every = parse(&arg, &value),
+            "--unsolvable-every" => script.unsolvable_every = parse(&arg, &value),
+            "--bad-hash-every" => script.bad_hash_every = parse(&arg, &value),
+            "--drop-after" => script.drop_after = parse(&arg, &value),
+            "--stall-after" => script.stall_after = parse(&arg, &value),
+            _ => panic!("Unknown option {}", arg),
+        }
+    }
+    script
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| panic!("Invalid value for {}: {}", arg, value))
+}
+
+#[tokio::main]
+async fn main() {
+    let script = Arc::new(script_from_args());
+    let listener = TcpListener::bind(("127.0.0.1", script.port))
+        .await
+        .expect("Bind mock pool");
+    println!(
+        "Mock pool listening on {} with {:?}",
+        listener.local_addr().expect("Local address"),
+        script
+    );
+    pool::run(listener, script).await;
+}
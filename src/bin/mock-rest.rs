@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use multi_duino::mock::rest;
+use tokio::net::TcpListener;
+
+// Local stand-in for the DUCO REST API serving JSON fixtures, see README.md
+#[derive(Debug, Clone)]
+struct Script {
+    port: u16,
+    fixtures: PathBuf,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Script {
+            port: 8080,
+            fixtures: PathBuf::from("fixtures"),
+        }
+    }
+}
+
+impl Script {
+    fn from_args() -> Self {
+        let mut script = Script::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .unwrap_or_else(|| panic!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--port" => {
+                    script.port = value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("Invalid value for {}: {}", arg, value))
+                }
+                "--fixtures" => script.fixtures = PathBuf::from(value),
+                _ => panic!("Unknown option {}", arg),
+            }
+        }
+        script
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let script = Script::from_args();
+    let listener = TcpListener::bind(("127.0.0.1", script.port))
+        .await
+        .expect("Bind mock REST server");
+    println!(
+        "Mock REST server listening on {} with {:?}",
+        listener.local_addr().expect("Local address"),
+        script
+    );
+    rest::run(listener, script.fixtures).await;
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use sha1::{Digest, Sha1};
+use xxhash_rust::xxh64::Xxh64;
+
+// Seed the pool uses for xxhash jobs
+pub const XXHASH_SEED: u64 = 2811;
+
+// Nonces checked between looks at the cancel flag
+const CANCEL_CHECK_INTERVAL: u64 = 4096;
+
+// A decoded job that nonces can be checked against
+pub trait Solver: Send + Sync {
+    fn check(&self, nonce: u64) -> bool;
+
+    // Gives up with None soon after cancel is set
+    fn solve(&self, max_nonce: u64, cancel: &AtomicBool) -> Option<u64> {
+        for nonce in 0..=max_nonce {
+            if nonce % CANCEL_CHECK_INTERVAL == 0 && cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            if self.check(nonce) {
+                return Some(nonce);
+            }
+        }
+        None
+    }
+}
+
+// DUCO-S1 hasher that absorbs the last block hash once and reuses that SHA-1 midstate
+// for every nonce, so checking a nonce neither allocates nor re-hashes the prefix.
+#[derive(Clone)]
+pub struct DucoS1Hasher {
+    midstate: Sha1,
+    expected: [u8; 20],
+}
+
+impl DucoS1Hasher {
+    pub fn new(last_hash: &str, expected: [u8; 20]) -> Self {
+        let mut midstate = Sha1::new();
+        midstate.update(last_hash.as_bytes());
+        DucoS1Hasher { midstate, expected }
+    }
+}
+
+impl Solver for DucoS1Hasher {
+    fn check(&self, nonce: u64) -> bool {
+        let mut buffer = [0u8; 20];
+        let digits = write_decimal(nonce, &mut buffer);
+        let mut hasher = self.midstate.clone();
+        hasher.update(digits);
+        hasher.finalize().as_slice() == self.expected
+    }
+}
+
+// Same midstate approach for xxhash jobs, which expect the 64 bit XXH64 of the same input
+#[derive(Clone)]
+pub struct XxhashHasher {
+    midstate: Xxh64,
+    expected: u64,
+}
+
+impl XxhashHasher {
+    pub fn new(last_hash: &str, expected: u64) -> Self {
+        let mut midstate = Xxh64::new(XXHASH_SEED);
+        midstate.update(last_hash.as_bytes());
+        XxhashHasher { midstate, expected }
+    }
+}
+
+impl Solver for XxhashHasher {
+    fn check(&self, nonce: u64) -> bool {
+        let mut buffer = [0u8; 20];
+        let digits = write_decimal(nonce, &mut buffer);
+        let mut hasher = self.midstate.clone();
+        hasher.update(digits);
+        hasher.digest() == self.expected
+    }
+}
+
+// Why the expected hash of a job couldn't be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DigestError {
+    Length { expected: usize, actual: usize },
+    NotHex { position: usize, character: char },
+}
+
+impl fmt::Display for DigestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DigestError::Length { expected, actual } => {
+                write!(f, "expected {} hex digits, got {}", expected, actual)
+            }
+            DigestError::NotHex {
+                position,
+                character,
+            } => write!(
+                f,
+                "{:?} at position {} is not a hex digit",
+                character, position
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DigestError {}
+
+// Decodes the hash a job expects, e.g. the 40 hex digits of a SHA-1 into 20 bytes
+pub fn decode_hex<const N: usize>(hex: &str) -> Result<[u8; N], DigestError> {
+    let length = hex.chars().count();
+    if length != N * 2 {
+        return Err(DigestError::Length {
+            expected: N * 2,
+            actual: length,
+        });
+    }
+    let mut digest = [0u8; N];
+    for (position, character) in hex.chars().enumerate() {
+        let value = character.to_digit(16).ok_or(DigestError::NotHex {
+            position,
+            character,
+        })? as u8;
+        digest[position / 2] |= if position % 2 == 0 { value << 4 } else { value };
+    }
+    Ok(digest)
+}
+
+// Writes the decimal digits of value to the end of buffer and returns them
+fn write_decimal(mut value: u64, buffer: &mut [u8; 20]) -> &[u8] {
+    let mut start = buffer.len();
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    &buffer[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static LAST_HASH: &str = "2b4b8c5f0c4b7d0cd4fd2c8f1e9a3b7a6c2d1e0f";
+
+    fn sha1(last_hash: &str, nonce: u64) -> [u8; 20] {
+        Sha1::digest(format!("{}{}", last_hash, nonce).as_bytes()).into()
+    }
+
+    #[test]
+    fn ducos1_finds_nonce() {
+        let hasher = DucoS1Hasher::new(LAST_HASH, sha1(LAST_HASH, 1234));
+        assert!(hasher.check(1234));
+        assert!(!hasher.check(1235));
+        assert_eq!(hasher.solve(2000, &AtomicBool::new(false)), Some(1234));
+        assert_eq!(hasher.solve(1000, &AtomicBool::new(false)), None);
+    }
+
+    #[test]
+    fn ducos1_finds_nonce_above_u16() {
+        // Difficulty 1000 jobs search up to 100 001, past the old u16 nonce range
+        let hasher = DucoS1Hasher::new(LAST_HASH, sha1(LAST_HASH, 99_999));
+        assert_eq!(hasher.solve(100_001, &AtomicBool::new(false)), Some(99_999));
+    }
+
+    #[test]
+    fn cancelled_solve_gives_up() {
+        let hasher = DucoS1Hasher::new(LAST_HASH, sha1(LAST_HASH, 99_999));
+        assert_eq!(hasher.solve(100_001, &AtomicBool::new(true)), None);
+    }
+
+    #[test]
+    fn decode_hex_round_trips_sha1() {
+        let digest: [u8; 20] = Sha1::digest(b"abc").into();
+        let hex = "a9993e364706816aba3e25717850c26c9cd0d89d";
+        assert_eq!(decode_hex::<20>(hex), Ok(digest));
+        assert_eq!(decode_hex::<20>(&hex.to_uppercase()), Ok(digest));
+    }
+
+    #[test]
+    fn decode_hex_rejects_wrong_length() {
+        assert_eq!(
+            decode_hex::<20>("a9993e"),
+            Err(DigestError::Length {
+                expected: 40,
+                actual: 6
+            })
+        );
+        assert_eq!(
+            decode_hex::<8>("a9993e364706816aba"),
+            Err(DigestError::Length {
+                expected: 16,
+                actual: 18
+            })
+        );
+        assert_eq!(
+            decode_hex::<8>(""),
+            Err(DigestError::Length {
+                expected: 16,
+                actual: 0
+            })
+        );
+    }
+
+    #[test]
+    fn decode_hex_rejects_non_hex() {
+        assert_eq!(
+            decode_hex::<8>("a9993e36470681zz"),
+            Err(DigestError::NotHex {
+                position: 14,
+                character: 'z'
+            })
+        );
+    }
+
+    #[test]
+    fn decode_hex_counts_characters_not_bytes() {
+        // 16 bytes but only 8 characters
+        assert_eq!(
+            decode_hex::<8>("éééééééé"),
+            Err(DigestError::Length {
+                expected: 16,
+                actual: 8
+            })
+        );
+        assert_eq!(
+            decode_hex::<8>("a9993é3647068169"),
+            Err(DigestError::NotHex {
+                position: 5,
+                character: 'é'
+            })
+        );
+    }
+
+    #[test]
+    fn write_decimal_matches_to_string() {
+        let mut buffer = [0u8; 20];
+        for value in [0, 7, 10, 65_535, 65_536, u64::MAX] {
+            assert_eq!(
+                write_decimal(value, &mut buffer),
+                value.to_string().as_bytes()
+            );
+        }
+    }
+}
@@ -0,0 +1,8 @@
+pub mod algorithm;
+pub mod api;
+pub mod hasher;
+pub mod miner;
+pub mod mock;
+pub mod protocol;
+pub mod selector;
+pub mod structs;
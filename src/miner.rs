@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use log::{error, info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::sync::{watch, RwLock};
+
+use crate::{api, hasher, protocol, structs};
+
+// Starts the given range of miner numbers for an account
+pub async fn spawn_miners(
+    state: &structs::SharedState,
+    numbers: RangeInclusive<u8>,
+    name: &str,
+    account: &structs::Account,
+    pool: &structs::PoolResult,
+    pools: &structs::PoolList,
+) {
+    let mut tasks = state.tasks.write().await;
+    for n in numbers {
+        let miner_id = format!("{}/{:03}", name, n);
+        let (stop, stop_rx) = watch::channel(false);
+        let handle = tokio::spawn(mine(
+            pool.clone(),
+            miner_id.clone(),
+            name.to_string(),
+            account.clone(),
+            state.clone(),
+            pools.clone(),
+            stop_rx,
+        ));
+        tasks.insert(miner_id, structs::MinerTask { stop, handle });
+    }
+}
+
+pub async fn stop_miners(tasks: Vec<structs::MinerTask>) {
+    for task in &tasks {
+        task.stop.send_replace(true);
+    }
+    for task in tasks {
+        if let Err(err) = task.handle.await {
+            error!("Mining task failed: {}", err);
+        }
+    }
+}
+
+// Pool for the nth connection attempt, failing over through the pool list, None if it's empty
+pub async fn pick_pool(pools: &structs::PoolList, attempt: usize) -> Option<structs::PoolResult> {
+    let slots = pools.pools.len() + pools.api_url.is_some() as usize;
+    if slots == 0 {
+        return None;
+    }
+    let mut slot = attempt % slots;
+    if let Some(api_url) = &pools.api_url {
+        if slot == 0 {
+            // Without static pools there is nothing to fall back to, so keep asking
+            let attempts = (!pools.pools.is_empty()).then_some(3);
+            if let Some(pool) = api::get_pool(api_url, attempts).await {
+                return Some(pool);
+            }
+            warn!("getPool isn't answering, falling back to the configured pools");
+            slot = 1;
+        }
+        slot -= 1;
+    }
+    Some(pools.pools[slot].clone())
+}
+
+pub async fn mine(
+    address: structs::PoolResult,
+    miner_id: String,
+    name: String,
+    config: structs::Account,
+    state: structs::SharedState,
+    pools: structs::PoolList,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let stats = &state.miners;
+    // Restarted miners keep counting where they left off
+    stats
+        .write()
+        .await
+        .entry(miner_id.clone())
+        .or_insert_with(|| structs::MinerStats {
+            account: name.clone(),
+            ..Default::default()
+        });
+
+    let mut rng = StdRng::from_entropy();
+    let ducoid = format!("DUCOID{:08X}{:08X}", rng.gen::<u32>(), rng.gen::<u32>());
+
+    let algorithm = config.algorithm.implementation();
+    let job_request = config.job_request(&name);
+
+    let mut address = address;
+    let mut pool_attempt: usize = 0;
+    let mut failures: u32 = 0;
+    'session: loop {
+        let connection = tokio::select! {
+            _ = stopped(&mut shutdown) => break 'session,
+            connection = async {
+                if failures > 0 {
+                    let backoff = Duration::from_secs(2u64.pow(failures.min(5)));
+                    warn!(
+                        "{}: Reconnecting in {}s (attempt #{})",
+                        miner_id,
+                        backoff.as_secs(),
+                        failures
+                    );
+                    tokio::time::sleep(backoff).await;
+                    pool_attempt += 1;
+                    // Without a pool list to fail over to, retry the same pool
+                    if let Some(pool) = pick_pool(&pools, pool_attempt).await {
+                        address = pool;
+                    }
+                    if let Some(miner) = stats.write().await.get_mut(&miner_id) {
+                        miner.reconnects += 1;
+                    }
+                }
+                connect(&address, &miner_id).await
+            } => connection,
+        };
+        let Some(mut connection) = connection else {
+            failures += 1;
+            continue;
+        };
+        let version = tokio::select! {
+            _ = stopped(&mut shutdown) => {
+                connection.close().await;
+                break 'session;
+            }
+            version = connection.read_version(config.job_timeout()) => version,
+        };
+        let server_version = match version {
+            Ok(version) if version.is_supported() => {
+                info!("{}: Pool server version {}", miner_id, version);
+                Some(version)
+            }
+            Ok(version) => {
+                warn!(
+                    "{}: Pool server version {} is outside the supported range {}.x to {}.x, the protocol may have changed",
+                    miner_id,
+                    version,
+                    protocol::SUPPORTED_MAJOR_VERSIONS.start(),
+                    protocol::SUPPORTED_MAJOR_VERSIONS.end()
+                );
+                Some(version)
+            }
+            // Mining can still work with an odd banner, so only warn
+            Err(protocol::ProtocolError::InvalidVersion(line)) => {
+                warn!(
+                    "{}: Unrecognized pool server version {:?}, the protocol may have changed",
+                    miner_id,
+                    line.trim()
+                );
+                None
+            }
+            Err(protocol::ProtocolError::TimedOut(timeout)) => {
+                warn!(
+                    "{}: Stalled, no server version within {}s",
+                    miner_id,
+                    timeout.as_secs()
+                );
+                mark_stalled(stats, &miner_id).await;
+                failures += 1;
+                continue 'session;
+            }
+            Err(err) => {
+                warn!("{}: Couldn't read server version: {}", miner_id, err);
+                failures += 1;
+                continue;
+            }
+        };
+        if let Some(miner) = stats.write().await.get_mut(&miner_id) {
+            miner.server_version = server_version;
+        }
+
+        loop {
+            let requested = tokio::select! {
+                _ = stopped(&mut shutdown) => {
+                    connection.close().await;
+                    break 'session;
+                }
+                requested = connection.request_job(&job_request, config.job_timeout()) => requested,
+            };
+            let job = match requested {
+                Ok(job) => job,
+                // Asking again right away could loop as fast as the pool answers
+                Err(protocol::ProtocolError::InvalidJob(line)) => {
+                    warn!("{}: Invalid job data: {}", miner_id, line.trim());
+                    connection.close().await;
+                    failures += 1;
+                    continue 'session;
+                }
+                Err(protocol::ProtocolError::TimedOut(timeout)) => {
+                    warn!(
+                        "{}: Stalled, no job within {}s",
+                        miner_id,
+                        timeout.as_secs()
+                    );
+                    mark_stalled(stats, &miner_id).await;
+                    failures += 1;
+                    continue 'session;
+                }
+                Err(err) => {
+                    warn!(
+                        "{}: Lost connection while requesting a job: {}",
+                        miner_id, err
+                    );
+                    failures += 1;
+                    continue 'session;
+                }
+            };
+            if let Some(miner) = stats.write().await.get_mut(&miner_id) {
+                miner.difficulty = job.difficulty;
+                miner.stalled = false;
+            }
+
+            // The pool waits for a share to this job, so a fresh session is the way out
+            let solver = match algorithm.decode(&job) {
+                Ok(solver) => solver,
+                Err(err) => {
+                    warn!(
+                        "{}: Invalid expected hash {:?} in {} job: {}",
+                        miner_id,
+                        job.expected_hash,
+                        algorithm.name(),
+                        err
+                    );
+                    connection.close().await;
+                    failures += 1;
+                    continue 'session;
+                }
+            };
+
+            let solved = tokio::select! {
+                _ = stopped(&mut shutdown) => {
+                    connection.close().await;
+                    break 'session;
+                }
+                solved = solve(&job, solver, &config) => solved,
+            };
+            // Submitting a made up nonce would only earn a rejection. The pool waits for a share
+            // until the connection is dropped, so start a fresh session to get another job
+            let Some((res, hashrate)) = solved else {
+                warn!(
+                    "{}: No nonce up to {} solves the {} job, reconnecting for a new one",
+                    miner_id,
+                    job.max_nonce(),
+                    algorithm.name()
+                );
+                if let Some(miner) = stats.write().await.get_mut(&miner_id) {
+                    miner.unsolved += 1;
+                }
+                connection.close().await;
+                continue 'session;
+            };
+
+            let share = protocol::ShareSubmission {
+                nonce: res,
+                hashrate,
+                software: config.software.clone().unwrap_or_default(),
+                rig_name: config.rig_name.clone().unwrap_or_default(),
+                ducoid: ducoid.clone(),
+            };
+            let submitted = Instant::now();
+            let feedback = match connection
+                .submit_share(&share, config.feedback_timeout())
+                .await
+            {
+                Ok(feedback) => feedback,
+                Err(protocol::ProtocolError::InvalidFeedback(line)) => {
+                    protocol::ShareFeedback::Bad(format!("Unexpected answer {}", line.trim()))
+                }
+                Err(protocol::ProtocolError::TimedOut(timeout)) => {
+                    warn!(
+                        "{}: Stalled, no share feedback within {}s",
+                        miner_id,
+                        timeout.as_secs()
+                    );
+                    mark_stalled(stats, &miner_id).await;
+                    failures += 1;
+                    continue 'session;
+                }
+                Err(err) => {
+                    warn!(
+                        "{}: Lost connection while submitting a share: {}",
+                        miner_id, err
+                    );
+                    failures += 1;
+                    continue 'session;
+                }
+            };
+            let round_trip = submitted.elapsed();
+            failures = 0;
+            state
+                .latencies
+                .write()
+                .await
+                .entry(address.address())
+                .or_insert_with(|| structs::PoolLatency {
+                    pool: address.clone(),
+                    ..Default::default()
+                })
+                .shares
+                .record(round_trip);
+            let (accepted, shares) = match stats.write().await.get_mut(&miner_id) {
+                Some(miner) => {
+                    miner.record_share(&feedback, hashrate, round_trip);
+                    (miner.accepted, miner.accepted + miner.rejected())
+                }
+                None => (0, 0),
+            };
+            let feedback_sanitized = match &feedback {
+                protocol::ShareFeedback::Good | protocol::ShareFeedback::Block => {
+                    "Accepted".to_string()
+                }
+                protocol::ShareFeedback::Bad(reason) => format!("Rejected: {}", reason),
+            };
+            info!(
+                "[{}] {}: [{}] {}/{} shares | {} H/s | {} difficulty | {:.1} ms",
+                Local::now().format("%H:%M:%S"),
+                miner_id,
+                feedback_sanitized,
+                accepted,
+                shares,
+                hashrate,
+                job.difficulty,
+                round_trip.as_secs_f64() * 1000.0
+            );
+        }
+    }
+    info!("{}: Stopped", miner_id);
+}
+
+async fn mark_stalled(stats: &Arc<RwLock<HashMap<String, structs::MinerStats>>>, miner_id: &str) {
+    if let Some(miner) = stats.write().await.get_mut(miner_id) {
+        miner.stalled = true;
+        miner.stalls += 1;
+    }
+}
+
+// Resolves once shutdown was requested or the sender is gone
+pub async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+// Solves a job according to the account's mining mode, returns the nonce and the hashrate to report
+// or None if no nonce in the job's range matches
+async fn solve(
+    job: &protocol::Job,
+    solver: Box<dyn hasher::Solver>,
+    config: &structs::Account,
+) -> Option<(u64, f64)> {
+    match config.mode {
+        structs::MiningMode::Emulated => {
+            let res = solve_emulated(
+                solver.as_ref(),
+                job.max_nonce(),
+                1000 / config.hashrate as u64,
+            )
+            .await?;
+            Some((res, config.hashrate as f64))
+        }
+        structs::MiningMode::Cpu => {
+            let max_nonce = job.max_nonce();
+            let cancel = CancelOnDrop(Arc::new(AtomicBool::new(false)));
+            let flag = Arc::clone(&cancel.0);
+            let (res, elapsed) = tokio::task::spawn_blocking(move || {
+                let started = Instant::now();
+                let res = solver.solve(max_nonce, &flag);
+                (res, started.elapsed())
+            })
+            .await
+            .expect("Join hashing worker");
+            let res = res?;
+            let hashes = res as f64 + 1.0;
+            let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+            Some((res, (hashes / seconds).round()))
+        }
+    }
+}
+
+// Dropping the solve future, e.g. on shutdown, only detaches the hashing worker,
+// so this tells the worker to give up as well
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+async fn connect(
+    address: &structs::PoolResult,
+    miner_id: &str,
+) -> Option<protocol::DucoConnection> {
+    match tokio::net::TcpStream::connect(format!("{}:{}", address.ip, address.port)).await {
+        Ok(stream) => {
+            info!(
+                "{}: Connected to {}/{}!",
+                miner_id, address.server, address.name
+            );
+            Some(protocol::DucoConnection::new(stream))
+        }
+        Err(err) => {
+            warn!(
+                "{}: Unable to setup mining node on {}:{}! {}",
+                miner_id, address.ip, address.port, err
+            );
+            None
+        }
+    }
+}
+
+// Checks one nonce at a time, sleeping in between to match the configured hashrate
+async fn solve_emulated(
+    solver: &dyn hasher::Solver,
+    max_nonce: u64,
+    hash_time: u64,
+) -> Option<u64> {
+    for nonce in 0..=max_nonce {
+        if solver.check(nonce) {
+            return Some(nonce);
+        }
+        tokio::time::sleep(Duration::from_micros(hash_time)).await;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::mock::rest;
+
+    fn static_pools(api_url: Option<String>) -> structs::PoolList {
+        structs::PoolList {
+            api_url,
+            pools: ["127.0.0.1:3001", "127.0.0.1:3002", "127.0.0.1:3003"]
+                .iter()
+                .filter_map(|address| structs::PoolResult::from_address(address))
+                .collect(),
+        }
+    }
+
+    async fn picks(pools: &structs::PoolList, attempts: usize) -> Vec<String> {
+        let mut picks = Vec::new();
+        for attempt in 0..attempts {
+            picks.push(pick_pool(pools, attempt).await.unwrap().address());
+        }
+        picks
+    }
+
+    #[tokio::test]
+    async fn empty_pool_list_has_nothing_to_pick() {
+        let pools = structs::PoolList {
+            api_url: None,
+            pools: Vec::new(),
+        };
+        assert!(pick_pool(&pools, 0).await.is_none());
+        assert!(pick_pool(&pools, 7).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn static_pools_are_used_in_turn() {
+        assert_eq!(
+            picks(&static_pools(None), 5).await,
+            [
+                "127.0.0.1:3001",
+                "127.0.0.1:3002",
+                "127.0.0.1:3003",
+                "127.0.0.1:3001",
+                "127.0.0.1:3002",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn get_pool_comes_before_the_static_pools() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        tokio::spawn(rest::run(listener, fixtures));
+
+        // The getPool fixture hands out 127.0.0.1:2811
+        assert_eq!(
+            picks(&static_pools(Some(api_url)), 5).await,
+            [
+                "127.0.0.1:2811",
+                "127.0.0.1:3001",
+                "127.0.0.1:3002",
+                "127.0.0.1:3003",
+                "127.0.0.1:2811",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_get_pool_falls_back_to_the_first_static_pool() {
+        // Nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let pools = static_pools(Some(api_url));
+        assert_eq!(
+            pick_pool(&pools, 0).await.unwrap().address(),
+            "127.0.0.1:3001"
+        );
+    }
+}
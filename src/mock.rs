@@ -0,0 +1,3 @@
+// Local stand-ins for the DUCO network used by the mock binaries and the tests
+pub mod pool;
+pub mod rest;
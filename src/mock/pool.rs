@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use rand::Rng;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use xxhash_rust::xxh64::xxh64;
+
+use crate::protocol;
+
+// Local stand-in for a DUCO pool, see README.md for the available options
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub port: u16,
+    pub version: String,
+    pub difficulty: u32,
+    pub bad_every: u64,
+    pub block_every: u64,
+    pub invalid_job_every: u64,
+    pub unsolvable_every: u64,
+    pub bad_hash_every: u64,
+    pub drop_after: u64,
+    pub stall_after: u64,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Script {
+            port: 2811,
+            version: "3.0".to_string(),
+            difficulty: 10,
+            bad_every: 0,
+            block_every: 0,
+            invalid_job_every: 0,
+            unsolvable_every: 0,
+            bad_hash_every: 0,
+            drop_after: 0,
+            stall_after: 0,
+        }
+    }
+}
+
+fn every(count: u64, interval: u64) -> bool {
+    interval > 0 && count.is_multiple_of(interval)
+}
+
+// Serves every miner that connects until the listener fails
+pub async fn run(listener: TcpListener, script: Arc<Script>) {
+    loop {
+        let Ok((socket, peer)) = listener.accept().await else {
+            return;
+        };
+        println!("{}: connected", peer);
+        let script = Arc::clone(&script);
+        tokio::spawn(async move {
+            let shares = serve(socket, &script).await;
+            println!("{}: disconnected after {} shares", peer, shares);
+        });
+    }
+}
+
+// Runs the job/share exchange for one miner, returns the number of shares it submitted
+async fn serve(mut socket: TcpStream, script: &Script) -> u64 {
+    let mut jobs = 0;
+    let mut shares = 0;
+    if send(&mut socket, &script.version).await.is_err() {
+        return shares;
+    }
+    loop {
+        let Some(request) = receive(&mut socket).await else {
+            return shares;
+        };
+        let Ok(request) = request.parse::<protocol::JobRequest>() else {
+            let _ = send(&mut socket, "BAD,Invalid job request").await;
+            return shares;
+        };
+        let digest = if request.keyword == "JOBXX" {
+            xxhash_digest
+        } else {
+            ducos1_digest
+        };
+
+        jobs += 1;
+        if every(jobs, script.invalid_job_every) {
+            if send(&mut socket, "not,a,job").await.is_err() {
+                return shares;
+            }
+            continue;
+        }
+        let mut job = generate_job(script.difficulty, digest);
+        if every(jobs, script.unsolvable_every) {
+            // A nonce past the job's range can't be found by a miner
+            job.expected_hash = digest(&job.last_hash, job.max_nonce() + 1);
+        }
+        if every(jobs, script.bad_hash_every) {
+            job.expected_hash.replace_range(..2, "zz");
+        }
+        if send(&mut socket, &job.to_string()).await.is_err() {
+            return shares;
+        }
+
+        let Some(submission) = receive(&mut socket).await else {
+            return shares;
+        };
+        shares += 1;
+        let feedback = match submission.parse::<protocol::ShareSubmission>() {
+            Ok(_) if every(shares, script.bad_every) => {
+                protocol::ShareFeedback::Bad("Injected rejection".to_string())
+            }
+            Ok(share) if digest(&job.last_hash, share.nonce) == job.expected_hash => {
+                if every(shares, script.block_every) {
+                    protocol::ShareFeedback::Block
+                } else {
+                    protocol::ShareFeedback::Good
+                }
+            }
+            Ok(_) => protocol::ShareFeedback::Bad("Incorrect result".to_string()),
+            Err(_) => protocol::ShareFeedback::Bad("Invalid share".to_string()),
+        };
+        if send(&mut socket, &feedback.to_string()).await.is_err() {
+            return shares;
+        }
+        if every(shares, script.drop_after) {
+            return shares;
+        }
+        if every(shares, script.stall_after) {
+            // Keep the connection open without ever answering again
+            while receive(&mut socket).await.is_some() {}
+            return shares;
+        }
+    }
+}
+
+// Jobs are built backwards from a random nonce so they always have a solution
+fn generate_job(difficulty: u32, digest: fn(&str, u64) -> String) -> protocol::Job {
+    let mut rng = rand::thread_rng();
+    let last_hash = (0..20)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect::<String>();
+    let nonce = rng.gen_range(0..=difficulty as u64 * 100);
+    protocol::Job {
+        expected_hash: digest(&last_hash, nonce),
+        last_hash,
+        difficulty,
+    }
+}
+
+fn ducos1_digest(last_hash: &str, nonce: u64) -> String {
+    Sha1::digest(format!("{}{}", last_hash, nonce).as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn xxhash_digest(last_hash: &str, nonce: u64) -> String {
+    format!(
+        "{:016x}",
+        xxh64(format!("{}{}", last_hash, nonce).as_bytes(), 2811)
+    )
+}
+
+async fn send(socket: &mut TcpStream, line: &str) -> std::io::Result<()> {
+    socket.write_all(format!("{}\n", line).as_bytes()).await
+}
+
+// Miners don't terminate their messages, so a single read is one message
+async fn receive(socket: &mut TcpStream) -> Option<String> {
+    let mut buffer = [0u8; 1024];
+    match socket.read(&mut buffer).await {
+        Ok(0) | Err(_) => None,
+        Ok(read) => Some(String::from_utf8_lossy(&buffer[..read]).to_string()),
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Serves the JSON fixtures in the directory until the listener fails
+pub async fn run(listener: TcpListener, fixtures: PathBuf) {
+    let fixtures = Arc::new(fixtures);
+    loop {
+        let Ok((socket, _)) = listener.accept().await else {
+            return;
+        };
+        let fixtures = Arc::clone(&fixtures);
+        tokio::spawn(async move { serve(socket, &fixtures).await });
+    }
+}
+
+async fn serve(mut socket: TcpStream, fixtures: &Path) {
+    let mut buffer = [0u8; 4096];
+    let read = match socket.read(&mut buffer).await {
+        Ok(0) | Err(_) => return,
+        Ok(read) => read,
+    };
+    let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+    let target = request
+        .lines()
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .unwrap_or("/");
+
+    let response = match fixture(fixtures, target) {
+        Some(body) => {
+            println!("200 {}", target);
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        None => {
+            println!("404 {}", target);
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        }
+    };
+    let _ = socket.write_all(response.as_bytes()).await;
+}
+
+// Per-name fixtures (users/<name>.json) take precedence over the shared one (users.json)
+fn fixture(fixtures: &Path, target: &str) -> Option<String> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (endpoint, name) = match path
+        .trim_start_matches('/')
+        .split('/')
+        .collect::<Vec<&str>>()[..]
+    {
+        ["mining_key"] => (
+            "mining_key",
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("u="))
+                .unwrap_or_default(),
+        ),
+        ["v3", "users", name] => ("users", name),
+        ["getPool"] => ("getPool", ""),
+        _ => return None,
+    };
+    if !name.is_empty() {
+        let specific = fixtures.join(endpoint).join(format!("{}.json", name));
+        if let Ok(body) = std::fs::read_to_string(specific) {
+            return Some(body);
+        }
+    }
+    std::fs::read_to_string(fixtures.join(format!("{}.json", endpoint))).ok()
+}
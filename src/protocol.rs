@@ -0,0 +1,514 @@
+use std::fmt;
+use std::io;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+// Version banner sent by the pool right after connecting, e.g. 3.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+// Major pool versions whose job and feedback format this miner understands
+pub const SUPPORTED_MAJOR_VERSIONS: RangeInclusive<u16> = 2..=4;
+
+impl ServerVersion {
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_MAJOR_VERSIONS.contains(&self.major)
+    }
+}
+
+// Anything after major.minor (a patch level or build suffix) is ignored
+impl FromStr for ServerVersion {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || ProtocolError::InvalidVersion(line.to_string());
+        let mut parts = sanitize(line).split('.');
+        let major = parts
+            .next()
+            .and_then(|major| major.parse().ok())
+            .ok_or_else(invalid)?;
+        let minor = match parts.next() {
+            Some(minor) => minor.parse().map_err(|_| invalid())?,
+            None => 0,
+        };
+        Ok(ServerVersion { major, minor })
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+// {keyword},{username},{difficulty},{key}, the keyword picks the hashing algorithm
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobRequest {
+    pub keyword: String,
+    pub username: String,
+    pub difficulty: String,
+    pub key: String,
+}
+
+impl fmt::Display for JobRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.keyword, self.username, self.difficulty, self.key
+        )
+    }
+}
+
+impl FromStr for JobRequest {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let parts = sanitize(line).split(',').collect::<Vec<&str>>();
+        match parts.as_slice() {
+            [keyword @ ("JOB" | "JOBXX"), username, difficulty, key] => Ok(JobRequest {
+                keyword: keyword.to_string(),
+                username: username.to_string(),
+                difficulty: difficulty.to_string(),
+                key: key.to_string(),
+            }),
+            _ => Err(ProtocolError::InvalidJobRequest(line.to_string())),
+        }
+    }
+}
+
+// {last_hash},{expected_hash},{difficulty}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub last_hash: String,
+    pub expected_hash: String,
+    pub difficulty: u32,
+}
+
+impl Job {
+    // Nonces are searched from 0 up to and including this
+    pub fn max_nonce(&self) -> u64 {
+        self.difficulty as u64 * 100 + 1
+    }
+}
+
+impl FromStr for Job {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let parts = sanitize(line).split(',').collect::<Vec<&str>>();
+        let [last_hash, expected_hash, difficulty] = parts.as_slice() else {
+            return Err(ProtocolError::InvalidJob(line.to_string()));
+        };
+        let difficulty = difficulty
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| ProtocolError::InvalidJob(line.to_string()))?;
+        Ok(Job {
+            last_hash: last_hash.to_string(),
+            expected_hash: expected_hash.to_string(),
+            difficulty,
+        })
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{}",
+            self.last_hash, self.expected_hash, self.difficulty
+        )
+    }
+}
+
+// {nonce},{hashrate},{software},{rig_name},{ducoid}
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareSubmission {
+    pub nonce: u64,
+    pub hashrate: f64,
+    pub software: String,
+    pub rig_name: String,
+    pub ducoid: String,
+}
+
+impl fmt::Display for ShareSubmission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{}",
+            self.nonce, self.hashrate, self.software, self.rig_name, self.ducoid
+        )
+    }
+}
+
+impl FromStr for ShareSubmission {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let parts = sanitize(line).split(',').collect::<Vec<&str>>();
+        let [nonce, hashrate, software, rig_name, ducoid] = parts.as_slice() else {
+            return Err(ProtocolError::InvalidShare(line.to_string()));
+        };
+        Ok(ShareSubmission {
+            nonce: nonce
+                .parse()
+                .map_err(|_| ProtocolError::InvalidShare(line.to_string()))?,
+            hashrate: hashrate
+                .parse()
+                .map_err(|_| ProtocolError::InvalidShare(line.to_string()))?,
+            software: software.to_string(),
+            rig_name: rig_name.to_string(),
+            ducoid: ducoid.to_string(),
+        })
+    }
+}
+
+// Pool answer to a submitted share, rejections carry the reason sent after BAD,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareFeedback {
+    Good,
+    Block,
+    Bad(String),
+}
+
+impl FromStr for ShareFeedback {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let feedback = sanitize(line);
+        let (status, reason) = feedback.split_once(',').unwrap_or((feedback, ""));
+        match status {
+            "GOOD" => Ok(ShareFeedback::Good),
+            "BLOCK" => Ok(ShareFeedback::Block),
+            "BAD" if reason.trim().is_empty() => Ok(ShareFeedback::Bad("Unknown".to_string())),
+            "BAD" => Ok(ShareFeedback::Bad(reason.trim().to_string())),
+            _ => Err(ProtocolError::InvalidFeedback(line.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ShareFeedback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareFeedback::Good => write!(f, "GOOD"),
+            ShareFeedback::Block => write!(f, "BLOCK"),
+            ShareFeedback::Bad(reason) => write!(f, "BAD,{}", reason),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    Closed,
+    TimedOut(Duration),
+    InvalidVersion(String),
+    InvalidJobRequest(String),
+    InvalidJob(String),
+    InvalidShare(String),
+    InvalidFeedback(String),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(err) => write!(f, "socket error: {}", err),
+            ProtocolError::Closed => write!(f, "connection closed by pool"),
+            ProtocolError::TimedOut(timeout) => {
+                write!(f, "no answer within {}s", timeout.as_secs())
+            }
+            ProtocolError::InvalidVersion(line) => write!(f, "invalid version: {}", line.trim()),
+            ProtocolError::InvalidJobRequest(line) => {
+                write!(f, "invalid job request: {}", line.trim())
+            }
+            ProtocolError::InvalidJob(line) => write!(f, "invalid job data: {}", line.trim()),
+            ProtocolError::InvalidShare(line) => write!(f, "invalid share: {}", line.trim()),
+            ProtocolError::InvalidFeedback(line) => {
+                write!(f, "invalid feedback: {}", line.trim())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<io::Error> for ProtocolError {
+    fn from(err: io::Error) -> Self {
+        ProtocolError::Io(err)
+    }
+}
+
+// The pool pads some answers with NUL bytes, strip them along with whitespace
+fn sanitize(line: &str) -> &str {
+    line.trim_matches(|c: char| c == '\0' || c.is_whitespace())
+}
+
+// Line based codec for the miner side of a pool connection
+pub struct DucoConnection {
+    stream: BufReader<TcpStream>,
+}
+
+impl DucoConnection {
+    pub fn new(stream: TcpStream) -> Self {
+        DucoConnection {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    pub async fn read_version(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<ServerVersion, ProtocolError> {
+        self.read_line_within(timeout).await?.parse()
+    }
+
+    pub async fn request_job(
+        &mut self,
+        request: &JobRequest,
+        timeout: Duration,
+    ) -> Result<Job, ProtocolError> {
+        self.write(&request.to_string()).await?;
+        self.read_line_within(timeout).await?.parse()
+    }
+
+    pub async fn submit_share(
+        &mut self,
+        share: &ShareSubmission,
+        timeout: Duration,
+    ) -> Result<ShareFeedback, ProtocolError> {
+        self.write(&share.to_string()).await?;
+        self.read_line_within(timeout).await?.parse()
+    }
+
+    pub async fn close(mut self) {
+        let _ = self.stream.get_mut().shutdown().await;
+    }
+
+    async fn write(&mut self, message: &str) -> Result<(), ProtocolError> {
+        self.stream.write_all(message.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn read_line(&mut self) -> Result<String, ProtocolError> {
+        let mut buffer = Vec::new();
+        if self.stream.read_until(0x0A, &mut buffer).await? == 0 {
+            return Err(ProtocolError::Closed);
+        }
+        Ok(String::from_utf8_lossy(&buffer).to_string())
+    }
+
+    async fn read_line_within(&mut self, timeout: Duration) -> Result<String, ProtocolError> {
+        tokio::time::timeout(timeout, self.read_line())
+            .await
+            .map_err(|_| ProtocolError::TimedOut(timeout))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_version_round_trip() {
+        let version: ServerVersion = "3.0\n".parse().unwrap();
+        assert_eq!(version, ServerVersion { major: 3, minor: 0 });
+        assert_eq!(version.to_string(), "3.0");
+        assert!(version.is_supported());
+    }
+
+    #[test]
+    fn server_version_ignores_padding_and_suffix() {
+        assert_eq!(
+            "\0\x004.2.1-beta\0\n".parse::<ServerVersion>().unwrap(),
+            ServerVersion { major: 4, minor: 2 }
+        );
+        assert_eq!(
+            "5".parse::<ServerVersion>().unwrap(),
+            ServerVersion { major: 5, minor: 0 }
+        );
+        assert!(!ServerVersion { major: 5, minor: 0 }.is_supported());
+    }
+
+    #[test]
+    fn server_version_rejects_garbage() {
+        for line in ["", "garbage", "3.x", "\0\0\n"] {
+            assert!(
+                matches!(
+                    line.parse::<ServerVersion>(),
+                    Err(ProtocolError::InvalidVersion(_))
+                ),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn job_request_round_trip() {
+        for keyword in ["JOB", "JOBXX"] {
+            let request = JobRequest {
+                keyword: keyword.to_string(),
+                username: "youraccount".to_string(),
+                difficulty: "LOW".to_string(),
+                key: "secret".to_string(),
+            };
+            let line = request.to_string();
+            assert_eq!(line, format!("{},youraccount,LOW,secret", keyword));
+            assert_eq!(line.parse::<JobRequest>().unwrap(), request);
+        }
+    }
+
+    #[test]
+    fn job_request_rejects_unknown_keyword_and_field_count() {
+        for line in [
+            "JOBX,youraccount,LOW,secret",
+            "JOB,youraccount,LOW",
+            "JOB,youraccount,LOW,secret,extra",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    line.parse::<JobRequest>(),
+                    Err(ProtocolError::InvalidJobRequest(_))
+                ),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn job_round_trip() {
+        let job: Job = "abc,def,10\n".parse().unwrap();
+        assert_eq!(
+            job,
+            Job {
+                last_hash: "abc".to_string(),
+                expected_hash: "def".to_string(),
+                difficulty: 10,
+            }
+        );
+        assert_eq!(job.to_string(), "abc,def,10");
+        assert_eq!(job.to_string().parse::<Job>().unwrap(), job);
+    }
+
+    #[test]
+    fn job_strips_nul_padding() {
+        let job: Job = "abc,def,10\0\0\0\n".parse().unwrap();
+        assert_eq!(job.difficulty, 10);
+    }
+
+    #[test]
+    fn job_accepts_difficulties_above_655() {
+        assert_eq!("abc,def,1000".parse::<Job>().unwrap().difficulty, 1000);
+        let job: Job = "abc,def,4294967295".parse().unwrap();
+        assert_eq!(job.difficulty, u32::MAX);
+        assert_eq!(job.max_nonce(), u32::MAX as u64 * 100 + 1);
+        assert!("abc,def,4294967296".parse::<Job>().is_err());
+    }
+
+    #[test]
+    fn job_rejects_wrong_field_count_and_difficulty() {
+        for line in ["abc,def", "abc,def,10,11", "abc,def,ten", "abc,def,-1", ""] {
+            assert!(
+                matches!(line.parse::<Job>(), Err(ProtocolError::InvalidJob(_))),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn share_submission_round_trip() {
+        let share = ShareSubmission {
+            nonce: 123,
+            hashrate: 340.5,
+            software: "MultiDuino 0.1.0".to_string(),
+            rig_name: "desktop".to_string(),
+            ducoid: "DUCOID0123456789ABCDEF".to_string(),
+        };
+        let line = share.to_string();
+        assert_eq!(
+            line,
+            "123,340.5,MultiDuino 0.1.0,desktop,DUCOID0123456789ABCDEF"
+        );
+        assert_eq!(line.parse::<ShareSubmission>().unwrap(), share);
+    }
+
+    #[test]
+    fn share_submission_rejects_bad_fields() {
+        for line in [
+            "123,340,MultiDuino,desktop",
+            "123,340,MultiDuino,desktop,DUCOID,extra",
+            "nonce,340,MultiDuino,desktop,DUCOID",
+            "123,fast,MultiDuino,desktop,DUCOID",
+        ] {
+            assert!(
+                matches!(
+                    line.parse::<ShareSubmission>(),
+                    Err(ProtocolError::InvalidShare(_))
+                ),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn share_feedback_round_trip() {
+        for feedback in [
+            ShareFeedback::Good,
+            ShareFeedback::Block,
+            ShareFeedback::Bad("Incorrect result".to_string()),
+        ] {
+            assert_eq!(
+                feedback.to_string().parse::<ShareFeedback>().unwrap(),
+                feedback
+            );
+        }
+    }
+
+    #[test]
+    fn share_feedback_parses_padded_lines_and_missing_reason() {
+        assert_eq!(
+            "GOOD\0\0\n".parse::<ShareFeedback>().unwrap(),
+            ShareFeedback::Good
+        );
+        assert_eq!(
+            "BAD\n".parse::<ShareFeedback>().unwrap(),
+            ShareFeedback::Bad("Unknown".to_string())
+        );
+        assert_eq!(
+            "BAD, \n".parse::<ShareFeedback>().unwrap(),
+            ShareFeedback::Bad("Unknown".to_string())
+        );
+        assert_eq!(
+            "BAD,Too low difficulty\n".parse::<ShareFeedback>().unwrap(),
+            ShareFeedback::Bad("Too low difficulty".to_string())
+        );
+    }
+
+    #[test]
+    fn share_feedback_rejects_unknown_keyword() {
+        for line in ["OK", "good", "", "GOODBYE"] {
+            assert!(
+                matches!(
+                    line.parse::<ShareFeedback>(),
+                    Err(ProtocolError::InvalidFeedback(_))
+                ),
+                "{:?}",
+                line
+            );
+        }
+    }
+}
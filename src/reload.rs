@@ -0,0 +1,157 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use log::{error, info, warn};
+use tokio::sync::{watch, RwLock};
+
+use multi_duino::structs::{self, DuinoConfig};
+use multi_duino::{api, miner, selector};
+
+static POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// Polls the config file and applies changes to the running miners and the dashboard
+pub async fn watch_config(
+    cli: structs::Cli,
+    settings: Arc<RwLock<DuinoConfig>>,
+    state: structs::SharedState,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut last_modified = modified(&cli.config);
+    loop {
+        tokio::select! {
+            _ = miner::stopped(&mut shutdown) => return,
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+        let current = modified(&cli.config);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        let new_settings = match cli.read_config(&crate::LOGGER) {
+            Ok(new_settings) => new_settings,
+            Err(errors) => {
+                for err in errors {
+                    error!(
+                        "RELOAD: Keeping the running config, {}: {}",
+                        cli.config, err
+                    );
+                }
+                continue;
+            }
+        };
+        let diff = settings.read().await.diff(&new_settings);
+        if diff.is_empty() {
+            info!("RELOAD: {} changed, no miners affected", cli.config);
+        } else {
+            // Starting an account waits for the REST API, which may never answer
+            tokio::select! {
+                _ = miner::stopped(&mut shutdown) => return,
+                _ = apply(&diff, &new_settings, &state) => {}
+            }
+        }
+        *settings.write().await = new_settings.clone();
+        tokio::select! {
+            _ = miner::stopped(&mut shutdown) => return,
+            _ = api::refresh_accounts(&state, &new_settings) => {}
+        }
+    }
+}
+
+async fn apply(diff: &structs::ConfigDiff, settings: &DuinoConfig, state: &structs::SharedState) {
+    for name in &diff.removed {
+        info!("RELOAD: Account {} removed, stopping its miners", name);
+        stop_account(state, name, 1).await;
+        forget_miners(state, name, 0).await;
+    }
+    for name in &diff.changed {
+        info!("RELOAD: Account {} changed, restarting its miners", name);
+        stop_account(state, name, 1).await;
+        forget_miners(state, name, settings.accounts[name].miners).await;
+        start_account(state, settings, name, 1).await;
+    }
+    for name in &diff.added {
+        info!("RELOAD: Account {} added", name);
+        start_account(state, settings, name, 1).await;
+    }
+    for (name, old, new) in &diff.resized {
+        info!(
+            "RELOAD: Account {} changed from {} to {} miners",
+            name, old, new
+        );
+        if new > old {
+            start_account(state, settings, name, old + 1).await;
+        } else {
+            stop_account(state, name, new + 1).await;
+            forget_miners(state, name, *new).await;
+        }
+    }
+}
+
+// Starts the miners of an account from the given miner number on
+async fn start_account(
+    state: &structs::SharedState,
+    settings: &DuinoConfig,
+    name: &String,
+    first: u8,
+) {
+    let account = &settings.accounts[name];
+    if !api::check_user(&settings.api_url, name, &account.key).await {
+        warn!(
+            "RELOAD: Account {} either doesn't exist or has invalid mining key",
+            name
+        );
+        return;
+    }
+    let Some(pool) = selector::select_pool(settings, name, account, &state.latencies).await else {
+        warn!("RELOAD: Account {} has no pool to mine on", name);
+        return;
+    };
+    miner::spawn_miners(
+        state,
+        first..=account.miners,
+        name,
+        account,
+        &pool,
+        &settings.pool_list(),
+    )
+    .await;
+}
+
+// Stops the miners of an account from the given miner number on
+async fn stop_account(state: &structs::SharedState, name: &str, first: u8) {
+    let stopping = {
+        let mut tasks = state.tasks.write().await;
+        let ids: Vec<String> = tasks
+            .keys()
+            .filter(|id| miner_number(id, name).is_some_and(|n| n >= first))
+            .cloned()
+            .collect();
+        ids.iter()
+            .filter_map(|id| tasks.remove(id))
+            .collect::<Vec<structs::MinerTask>>()
+    };
+    miner::stop_miners(stopping).await;
+}
+
+// Restarted miners keep their stats, the ones numbered above keep are gone for good
+async fn forget_miners(state: &structs::SharedState, name: &str, keep: u8) {
+    state
+        .miners
+        .write()
+        .await
+        .retain(|id, _| miner_number(id, name).is_none_or(|n| n <= keep));
+}
+
+// The number of an account/number miner id if it belongs to the account
+fn miner_number(id: &str, name: &str) -> Option<u8> {
+    id.rsplit_once('/')
+        .filter(|(account, _)| *account == name)
+        .and_then(|(_, number)| number.parse().ok())
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use log::{info, warn};
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+
+use crate::protocol::{DucoConnection, JobRequest, ProtocolError};
+use crate::structs::{self, DuinoConfig, PoolLatency, PoolResult};
+
+static PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Probes the known pools and returns the fastest healthy one, preferring the configured region,
+// None if there are no pools to pick from
+pub async fn select_pool(
+    settings: &DuinoConfig,
+    name: &str,
+    account: &structs::Account,
+    latencies: &Arc<RwLock<HashMap<String, PoolLatency>>>,
+) -> Option<PoolResult> {
+    let pools = settings.pool_list();
+    let candidates = candidates(&pools, settings.pool_probes).await;
+    if candidates.is_empty() {
+        return crate::miner::pick_pool(&pools, 0).await;
+    }
+
+    let request = account.job_request(name);
+    let probes = join_all(candidates.into_iter().map(|pool| probe(pool, &request))).await;
+    {
+        let mut latencies = latencies.write().await;
+        for probe in &probes {
+            let latency = latencies
+                .entry(probe.pool.address())
+                .or_insert_with(|| probe.clone());
+            latency.probed = probe.probed;
+            latency.connect = probe.connect;
+            latency.job = probe.job;
+        }
+    }
+
+    let healthy: Vec<&PoolLatency> = probes.iter().filter(|probe| probe.is_healthy()).collect();
+    let in_region = |probe: &&&PoolLatency| {
+        settings
+            .pool_region
+            .as_ref()
+            .is_some_and(|region| probe.pool.region.eq_ignore_ascii_case(region))
+    };
+    let best = healthy
+        .iter()
+        .filter(in_region)
+        .min_by_key(|probe| probe.total())
+        .or_else(|| healthy.iter().min_by_key(|probe| probe.total()));
+    match best {
+        Some(best) => {
+            info!(
+                "{}: Selected pool {}/{} ({:.1} ms connect, {:.1} ms job)",
+                name,
+                best.pool.server,
+                best.pool.name,
+                best.connect.unwrap_or_default().as_secs_f64() * 1000.0,
+                best.job.unwrap_or_default().as_secs_f64() * 1000.0
+            );
+            Some(best.pool.clone())
+        }
+        None => {
+            warn!(
+                "{}: None of the {} probed pools handed out a job, falling back to the pool list",
+                name,
+                probes.len()
+            );
+            crate::miner::pick_pool(&pools, 0).await
+        }
+    }
+}
+
+// getPool answers followed by the configured pools, each address only once
+async fn candidates(pools: &structs::PoolList, probes: u8) -> Vec<PoolResult> {
+    let mut candidates = Vec::new();
+    if let Some(api_url) = &pools.api_url {
+        for _ in 0..probes {
+            match crate::api::get_pool(api_url, Some(3)).await {
+                Some(pool) => candidates.push(pool),
+                None => break,
+            }
+        }
+    }
+    candidates.extend(pools.pools.iter().cloned());
+    let mut seen = HashSet::new();
+    candidates.retain(|pool| seen.insert(pool.address()));
+    candidates
+}
+
+// Times the TCP connect and one job request, then hangs up
+async fn probe(pool: PoolResult, request: &JobRequest) -> PoolLatency {
+    let mut latency = PoolLatency {
+        pool,
+        probed: true,
+        ..Default::default()
+    };
+    let address = latency.pool.address();
+    let _ = tokio::time::timeout(PROBE_TIMEOUT, async {
+        let started = Instant::now();
+        let stream = TcpStream::connect(&address).await.ok()?;
+        latency.connect = Some(started.elapsed());
+        let mut connection = DucoConnection::new(stream);
+        match connection.read_version(PROBE_TIMEOUT).await {
+            // Mining can still work with an odd banner, as in mine()
+            Ok(_) | Err(ProtocolError::InvalidVersion(_)) => {}
+            Err(_) => return None,
+        }
+        let started = Instant::now();
+        connection.request_job(request, PROBE_TIMEOUT).await.ok()?;
+        latency.job = Some(started.elapsed());
+        connection.close().await;
+        Some(())
+    })
+    .await;
+    latency
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::mock::pool::{self, Script};
+
+    async fn probe_mock_pool(version: &str) -> PoolLatency {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let script = Script {
+            version: version.to_string(),
+            ..Default::default()
+        };
+        tokio::spawn(pool::run(listener, Arc::new(script)));
+        let account = structs::Account {
+            key: "secret".to_string(),
+            ..Default::default()
+        };
+        probe(
+            PoolResult::from_address(&address).unwrap(),
+            &account.job_request("tester"),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn probe_times_connect_and_job() {
+        let latency = probe_mock_pool("3.0").await;
+        assert!(latency.probed);
+        assert!(latency.connect.is_some());
+        assert!(latency.job.is_some());
+        assert!(latency.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn probe_tolerates_an_unrecognized_version() {
+        let latency = probe_mock_pool("Duino pool").await;
+        assert!(latency.job.is_some());
+        assert!(latency.is_healthy());
+    }
+}
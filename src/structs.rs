@@ -3,15 +3,30 @@ pub use self::account::RestAccount;
 //pub use self::account::AccountResult;
 //pub use self::account::AccountBalance;
 pub use self::account::AccountData;
+pub use self::account::Algorithm;
 pub use self::account::AccountCheck;
+pub use self::account::MiningMode;
+pub use self::cli::Cli;
+pub use self::config::ConfigDiff;
+pub use self::config::ConfigError;
 pub use self::config::DuinoConfig;
+pub use self::latency::LatencyStats;
+pub use self::miner::MinerStats;
+pub use self::miner::MinerTask;
+pub use self::pool::PoolLatency;
+pub use self::pool::PoolList;
 pub use self::pool::PoolResult;
+pub use self::shared::SharedState;
 pub use self::tui::Event;
 pub use self::tui::MenuItem;
 pub use self::veclog::VecLogger;
 
 mod account;
+mod cli;
 mod config;
+mod latency;
+mod miner;
 mod pool;
+mod shared;
 mod tui;
 mod veclog;
\ No newline at end of file
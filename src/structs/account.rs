@@ -1,9 +1,13 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use serde_json::Value;
 
+use crate::algorithm::{self, HashAlgorithm};
+use crate::protocol::JobRequest;
+
 // REST Account
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RestAccount {
@@ -40,22 +44,136 @@ pub struct AccountBalance {
 // TUI Account
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct AccountData {
-    pub hashrate: u16,
-    pub miners: u8,
-    pub connected: u8,
+    // Wide enough for the totals over every account
+    pub hashrate: u64,
+    pub miners: u32,
+    pub connected: u32,
     pub current_balance: f64,
     pub status: String,
     pub estimated_balance: f64,
     pub staked: f64,
     pub warnings: u32,
+    pub tier: String,
 }
 
 // Config Account
-#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Account {
     pub key: String,
     pub hashrate: u16,
-    pub miners: u8
+    pub miners: u8,
+    #[serde(default)]
+    pub mode: MiningMode,
+    #[serde(default)]
+    pub job_type: JobType,
+    // Only PC miners pick a tier, LOW if unset
+    pub difficulty: Option<DifficultyTier>,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    pub rig_name: Option<String>,
+    pub software: Option<String>,
+    // Seconds to wait for a job or share feedback, the global setting is used if unset
+    pub job_timeout: Option<u64>,
+    pub feedback_timeout: Option<u64>,
+}
+
+impl Account {
+    // Boards request their own difficulty class, PC miners pick a tier
+    pub fn requested_difficulty(&self) -> &'static str {
+        match self.job_type {
+            JobType::Pc => self.difficulty.unwrap_or_default().as_str(),
+            job_type => job_type.as_str(),
+        }
+    }
+
+    pub fn job_request(&self, username: &str) -> JobRequest {
+        JobRequest {
+            keyword: self.algorithm.implementation().job_request().to_string(),
+            username: username.to_string(),
+            difficulty: self.requested_difficulty().to_string(),
+            key: self.key.clone(),
+        }
+    }
+
+    pub fn job_timeout(&self) -> Duration {
+        Duration::from_secs(self.job_timeout.unwrap_or_default())
+    }
+
+    pub fn feedback_timeout(&self) -> Duration {
+        Duration::from_secs(self.feedback_timeout.unwrap_or_default())
+    }
+}
+
+// Emulated mining sleeps to match the configured hashrate, cpu mining hashes at full speed
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MiningMode {
+    #[default]
+    Emulated,
+    Cpu,
+}
+
+// Device class announced in the job request
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum JobType {
+    #[default]
+    Pc,
+    Avr,
+    Esp8266,
+    Esp32,
+}
+
+impl JobType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobType::Pc => "PC",
+            JobType::Avr => "AVR",
+            JobType::Esp8266 => "ESP8266",
+            JobType::Esp32 => "ESP32",
+        }
+    }
+}
+
+// Hashing algorithm of the jobs an account mines
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    #[serde(rename = "DUCO-S1")]
+    DucoS1,
+    #[serde(rename = "XXHASH")]
+    Xxhash,
+}
+
+impl Algorithm {
+    pub fn implementation(&self) -> &'static dyn HashAlgorithm {
+        match self {
+            Algorithm::DucoS1 => &algorithm::DucoS1,
+            Algorithm::Xxhash => &algorithm::Xxhash,
+        }
+    }
+}
+
+// Starting difficulty class requested by PC miners
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DifficultyTier {
+    #[default]
+    Low,
+    Medium,
+    Net,
+    Extreme,
+}
+
+impl DifficultyTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DifficultyTier::Low => "LOW",
+            DifficultyTier::Medium => "MEDIUM",
+            DifficultyTier::Net => "NET",
+            DifficultyTier::Extreme => "EXTREME",
+        }
+    }
 }
 
 // Account check
@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::config::{ConfigError, DuinoConfig};
+use super::veclog::VecLogger;
+
+// Command line arguments
+#[derive(Parser, Debug, Clone)]
+#[command(
+    name = "multi-duino",
+    version,
+    about = "Multi account Duino-Coin miner"
+)]
+pub struct Cli {
+    /// Path to the config file
+    #[arg(short, long, default_value = "conf.toml")]
+    pub config: String,
+    /// Only validate the config file and exit
+    #[arg(long)]
+    pub check_config: bool,
+    /// Run without the dashboard and print log lines to stdout, stop with Ctrl+C
+    #[arg(long)]
+    pub headless: bool,
+    /// Append every log line to this file
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Only mine with this account, can be repeated
+    #[arg(long, value_name = "ACCOUNT")]
+    pub only: Vec<String>,
+    /// Don't mine with this account, can be repeated
+    #[arg(long, value_name = "ACCOUNT")]
+    pub exclude: Vec<String>,
+    /// Override update_interval from the config file
+    #[arg(long, value_name = "SECONDS")]
+    pub update_interval: Option<u32>,
+}
+
+impl Cli {
+    // Applies the overrides and account filters to a freshly loaded config
+    pub fn apply(&self, settings: &mut DuinoConfig) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        for (flag, names) in [("--only", &self.only), ("--exclude", &self.exclude)] {
+            for name in names {
+                if !settings.accounts.contains_key(name) {
+                    errors.push(ConfigError::Invalid {
+                        key: format!("accounts.{}", name),
+                        message: format!("passed to {} but not configured", flag),
+                    });
+                }
+            }
+        }
+        settings.accounts.retain(|name, _| {
+            (self.only.is_empty() || self.only.contains(name)) && !self.exclude.contains(name)
+        });
+        if let Some(update_interval) = self.update_interval {
+            settings.update_interval = update_interval;
+        }
+        errors
+    }
+
+    // Loads, checks and completes the config, registering its keys with the logger to mask them
+    pub fn read_config(&self, logger: &VecLogger) -> Result<DuinoConfig, Vec<ConfigError>> {
+        let mut settings = DuinoConfig::load(&self.config)?;
+        let mut errors = self.apply(&mut settings);
+        errors.extend(settings.resolve_keys());
+        errors.extend(settings.validate());
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        settings.resolve_account_defaults();
+        logger.add_secrets(
+            settings
+                .accounts
+                .values()
+                .map(|account| account.key.clone()),
+        );
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_config_masks_the_configured_keys() {
+        let dir = std::env::temp_dir();
+        let key_file = dir.join(format!("multi-duino-cli-key-{}", std::process::id()));
+        let config = dir.join(format!("multi-duino-cli-{}.toml", std::process::id()));
+        std::fs::write(&key_file, "filesecretkey\n").unwrap();
+        std::fs::write(
+            &config,
+            format!(
+                "update_interval = 60
+                [accounts.alice]
+                key = \"plainsecretkey\"
+                hashrate = 100
+                miners = 1
+                [accounts.bob]
+                key = \"file:{}\"
+                hashrate = 100
+                miners = 1",
+                key_file.display()
+            ),
+        )
+        .unwrap();
+        let cli = Cli::parse_from(["multi-duino", "--config", config.to_str().unwrap()]);
+        let logger = VecLogger::default();
+        let settings = cli.read_config(&logger);
+        let _ = std::fs::remove_file(&key_file);
+        let _ = std::fs::remove_file(&config);
+
+        let settings = settings.unwrap();
+        assert_eq!(settings.accounts["bob"].key, "filesecretkey");
+        logger.log(format!(
+            "JOB,alice,LOW,plainsecretkey | JOB,bob,LOW,filesecretkey | {}",
+            key_file.display()
+        ));
+        let logs = logger.logs();
+        assert_eq!(
+            logs[0],
+            format!(
+                "JOB,alice,LOW,pl**** | JOB,bob,LOW,fi**** | {}",
+                key_file.display()
+            )
+        );
+    }
+}
@@ -1,11 +1,659 @@
+use std::fmt;
+
 use config::Map;
-use serde_derive::Serialize;
 use serde_derive::Deserialize;
+use serde_derive::Serialize;
 
 use super::account;
+use super::pool::{PoolList, PoolResult};
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct DuinoConfig {
     pub update_interval: u32,
-    pub accounts: Map<String, account::Account>
-}
\ No newline at end of file
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    #[serde(default = "default_rig_name")]
+    pub rig_name: String,
+    // Miner software name sent with every share, can be overridden per account
+    #[serde(default = "default_software")]
+    pub software: String,
+    #[serde(default = "default_pool_api")]
+    pub pool_api: bool,
+    #[serde(default)]
+    pub pools: Vec<String>,
+    // Number of getPool answers to probe before picking the fastest pool
+    #[serde(default = "default_pool_probes")]
+    pub pool_probes: u8,
+    // Pools in this region are picked over faster ones elsewhere
+    #[serde(default)]
+    pub pool_region: Option<String>,
+    // Seconds to wait for the pool to hand out a job or answer a share before reconnecting
+    #[serde(default = "default_timeout")]
+    pub job_timeout: u64,
+    #[serde(default = "default_timeout")]
+    pub feedback_timeout: u64,
+    pub accounts: Map<String, account::Account>,
+}
+
+fn default_api_url() -> String {
+    "https://server.duinocoin.com".to_string()
+}
+
+fn default_rig_name() -> String {
+    "MultiDuino".to_string()
+}
+
+fn default_software() -> String {
+    concat!("MultiDuino ", env!("CARGO_PKG_VERSION")).to_string()
+}
+
+fn default_pool_api() -> bool {
+    true
+}
+
+fn default_pool_probes() -> u8 {
+    3
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+
+impl DuinoConfig {
+    pub fn load(path: &str) -> Result<DuinoConfig, Vec<ConfigError>> {
+        let config = config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()
+            .map_err(|err| vec![ConfigError::Load(err.to_string())])?;
+        let errors = out_of_range(&config);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        config
+            .try_deserialize::<DuinoConfig>()
+            .map_err(|err| vec![ConfigError::Load(err.to_string())])
+    }
+
+    // Replaces env:NAME and file:PATH key references with the secret they point to
+    pub fn resolve_keys(&mut self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        for (name, account) in self.accounts.iter_mut() {
+            let key = format!("accounts.{}.key", name);
+            let resolved = if let Some(variable) = account.key.strip_prefix("env:") {
+                std::env::var(variable).map_err(|_| {
+                    ConfigError::invalid(
+                        &key,
+                        &format!("environment variable {} is not set", variable),
+                    )
+                })
+            } else if let Some(path) = account.key.strip_prefix("file:") {
+                std::fs::read_to_string(path)
+                    .map(|secret| secret.trim().to_string())
+                    .map_err(|err| {
+                        ConfigError::invalid(&key, &format!("couldn't read {}: {}", path, err))
+                    })
+            } else {
+                continue;
+            };
+            match resolved {
+                Ok(secret) => account.key = secret,
+                Err(err) => errors.push(err),
+            }
+        }
+        errors
+    }
+
+    // Accounts without their own rig name, software or timeouts use the global ones
+    pub fn resolve_account_defaults(&mut self) {
+        for account in self.accounts.values_mut() {
+            account
+                .rig_name
+                .get_or_insert_with(|| self.rig_name.clone());
+            account
+                .software
+                .get_or_insert_with(|| self.software.clone());
+            account.job_timeout.get_or_insert(self.job_timeout);
+            account
+                .feedback_timeout
+                .get_or_insert(self.feedback_timeout);
+        }
+    }
+
+    pub fn pool_list(&self) -> PoolList {
+        PoolList {
+            api_url: self.pool_api.then(|| self.api_url.clone()),
+            pools: self
+                .pools
+                .iter()
+                .filter_map(|address| PoolResult::from_address(address))
+                .collect(),
+        }
+    }
+
+    // What has to happen to the running miners to go from this config to the new one
+    pub fn diff(&self, new: &DuinoConfig) -> ConfigDiff {
+        let mut diff = ConfigDiff::default();
+        for (name, account) in new.accounts.iter() {
+            match self.accounts.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(_)
+                    if self.api_url != new.api_url
+                        || self.pool_api != new.pool_api
+                        || self.pools != new.pools
+                        || self.pool_probes != new.pool_probes
+                        || self.pool_region != new.pool_region =>
+                {
+                    diff.changed.push(name.clone())
+                }
+                Some(old) if old.miners != account.miners => {
+                    let resized = account::Account {
+                        miners: old.miners,
+                        ..account.clone()
+                    };
+                    if &resized == old {
+                        diff.resized
+                            .push((name.clone(), old.miners, account.miners));
+                    } else {
+                        diff.changed.push(name.clone());
+                    }
+                }
+                Some(old) if old != account => diff.changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+        for name in self.accounts.keys() {
+            if !new.accounts.contains_key(name) {
+                diff.removed.push(name.clone());
+            }
+        }
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        diff.resized.sort();
+        diff
+    }
+
+    // Errors name the TOML key at fault
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        if !(10..=3600).contains(&self.update_interval) {
+            errors.push(ConfigError::invalid(
+                "update_interval",
+                "must be between 10 and 3600 seconds",
+            ));
+        }
+        if !self.api_url.starts_with("http://") && !self.api_url.starts_with("https://") {
+            errors.push(ConfigError::invalid(
+                "api_url",
+                "must start with http:// or https://",
+            ));
+        }
+        for (index, address) in self.pools.iter().enumerate() {
+            if PoolResult::from_address(address).is_none() {
+                errors.push(ConfigError::invalid(
+                    &format!("pools[{}]", index),
+                    "must be a host:port address",
+                ));
+            }
+        }
+        if !self.pool_api && self.pools.is_empty() {
+            errors.push(ConfigError::invalid(
+                "pools",
+                "at least one pool is required when pool_api is false",
+            ));
+        }
+        if !(1..=10).contains(&self.pool_probes) {
+            errors.push(ConfigError::invalid(
+                "pool_probes",
+                "must be between 1 and 10",
+            ));
+        }
+        errors.extend(validate_timeout("job_timeout", Some(self.job_timeout)));
+        errors.extend(validate_timeout(
+            "feedback_timeout",
+            Some(self.feedback_timeout),
+        ));
+        if self.rig_name.contains(',') {
+            errors.push(ConfigError::invalid("rig_name", "must not contain commas"));
+        }
+        if self.software.contains(',') {
+            errors.push(ConfigError::invalid("software", "must not contain commas"));
+        }
+        if self.accounts.is_empty() {
+            errors.push(ConfigError::invalid(
+                "accounts",
+                "at least one account is required",
+            ));
+        }
+
+        let mut names: Vec<&String> = self.accounts.keys().collect();
+        names.sort();
+        for (index, name) in names.iter().enumerate() {
+            let account = &self.accounts[*name];
+            let key = |field: &str| format!("accounts.{}.{}", name, field);
+            if let Some(duplicate) = names[..index]
+                .iter()
+                .find(|other| other.eq_ignore_ascii_case(name))
+            {
+                errors.push(ConfigError::invalid(
+                    &format!("accounts.{}", name),
+                    &format!("duplicates account {}", duplicate),
+                ));
+            }
+            if account.key.trim().is_empty() {
+                errors.push(ConfigError::invalid(&key("key"), "must not be empty"));
+            }
+            if account.job_type != account::JobType::Pc && account.difficulty.is_some() {
+                errors.push(ConfigError::invalid(
+                    &key("difficulty"),
+                    &format!(
+                        "only applies to PC miners, {} miners get their own difficulty",
+                        account.job_type.as_str()
+                    ),
+                ));
+            }
+            if account.hashrate == 0 {
+                errors.push(ConfigError::invalid(
+                    &key("hashrate"),
+                    "must be greater than 0",
+                ));
+            }
+            if account.miners == 0 {
+                errors.push(ConfigError::invalid(
+                    &key("miners"),
+                    "must be greater than 0",
+                ));
+            }
+            if account
+                .rig_name
+                .as_ref()
+                .is_some_and(|rig_name| rig_name.contains(','))
+            {
+                errors.push(ConfigError::invalid(
+                    &key("rig_name"),
+                    "must not contain commas",
+                ));
+            }
+            if account
+                .software
+                .as_ref()
+                .is_some_and(|software| software.contains(','))
+            {
+                errors.push(ConfigError::invalid(
+                    &key("software"),
+                    "must not contain commas",
+                ));
+            }
+            errors.extend(validate_timeout(&key("job_timeout"), account.job_timeout));
+            errors.extend(validate_timeout(
+                &key("feedback_timeout"),
+                account.feedback_timeout,
+            ));
+        }
+        errors
+    }
+}
+
+// config truncates integers that don't fit the field they go into, so 300 miners would start 44
+fn out_of_range(config: &config::Config) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let mut check = |key: &str, value: Option<i64>, max: i64| {
+        if value.is_some_and(|value| value > max) {
+            errors.push(ConfigError::invalid(
+                key,
+                &format!("must be at most {}", max),
+            ));
+        }
+    };
+    check(
+        "update_interval",
+        config.get_int("update_interval").ok(),
+        u32::MAX.into(),
+    );
+    check(
+        "pool_probes",
+        config.get_int("pool_probes").ok(),
+        u8::MAX.into(),
+    );
+    let mut accounts: Vec<(String, config::Value)> = config
+        .get_table("accounts")
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    accounts.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (name, account) in accounts {
+        let account = account.into_table().unwrap_or_default();
+        let int = |field: &str| {
+            account
+                .get(field)
+                .and_then(|value| value.clone().into_int().ok())
+        };
+        check(
+            &format!("accounts.{}.miners", name),
+            int("miners"),
+            u8::MAX.into(),
+        );
+        check(
+            &format!("accounts.{}.hashrate", name),
+            int("hashrate"),
+            u16::MAX.into(),
+        );
+    }
+    errors
+}
+
+fn validate_timeout(key: &str, timeout: Option<u64>) -> Option<ConfigError> {
+    timeout
+        .filter(|timeout| !(1..=600).contains(timeout))
+        .map(|_| ConfigError::invalid(key, "must be between 1 and 600 seconds"))
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    // Accounts whose miners have to be restarted
+    pub changed: Vec<String>,
+    // Accounts that only changed their number of miners, (name, old, new)
+    pub resized: Vec<(String, u8, u8)>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.resized.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    Load(String),
+    Invalid { key: String, message: String },
+}
+
+impl ConfigError {
+    fn invalid(key: &str, message: &str) -> Self {
+        ConfigError::Invalid {
+            key: key.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Load(message) => write!(f, "{}", message),
+            ConfigError::Invalid { key, message } => write!(f, "{}: {}", key, message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(miners: u8, hashrate: u16) -> account::Account {
+        account::Account {
+            key: "secret".to_string(),
+            miners,
+            hashrate,
+            ..Default::default()
+        }
+    }
+
+    fn settings(accounts: &[(&str, u8, u16)]) -> DuinoConfig {
+        DuinoConfig {
+            update_interval: 60,
+            api_url: default_api_url(),
+            rig_name: default_rig_name(),
+            software: default_software(),
+            pool_api: true,
+            pool_probes: default_pool_probes(),
+            job_timeout: default_timeout(),
+            feedback_timeout: default_timeout(),
+            accounts: accounts
+                .iter()
+                .map(|(name, miners, hashrate)| (name.to_string(), account(*miners, *hashrate)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keys_are_resolved_from_the_environment() {
+        let variable = format!("MULTI_DUINO_TEST_KEY_{}", std::process::id());
+        std::env::set_var(&variable, "envsecret");
+        let mut settings = settings(&[("alice", 1, 100), ("bob", 1, 100), ("carol", 1, 100)]);
+        settings.accounts.get_mut("alice").unwrap().key = format!("env:{}", variable);
+        settings.accounts.get_mut("bob").unwrap().key = format!("env:{}_UNSET", variable);
+        let errors = settings.resolve_keys();
+        std::env::remove_var(&variable);
+
+        assert_eq!(settings.accounts["alice"].key, "envsecret");
+        assert_eq!(settings.accounts["carol"].key, "secret");
+        assert_eq!(
+            errors,
+            [ConfigError::invalid(
+                "accounts.bob.key",
+                &format!("environment variable {}_UNSET is not set", variable)
+            )]
+        );
+    }
+
+    #[test]
+    fn keys_are_read_from_files() {
+        let path = std::env::temp_dir().join(format!("multi-duino-key-{}", std::process::id()));
+        std::fs::write(&path, "filesecret\n").unwrap();
+        let mut settings = settings(&[("alice", 1, 100), ("bob", 1, 100)]);
+        settings.accounts.get_mut("alice").unwrap().key = format!("file:{}", path.display());
+        settings.accounts.get_mut("bob").unwrap().key = format!("file:{}.missing", path.display());
+        let errors = settings.resolve_keys();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(settings.accounts["alice"].key, "filesecret");
+        assert_eq!(error_keys(errors), ["accounts.bob.key"]);
+        // The unresolved reference is kept, not replaced with an empty key
+        assert!(settings.accounts["bob"].key.starts_with("file:"));
+    }
+
+    #[test]
+    fn accounts_fall_back_to_the_global_software() {
+        let mut settings = settings(&[("alice", 1, 100), ("bob", 1, 100)]);
+        settings.accounts.get_mut("bob").unwrap().software = Some("Bob's miner".to_string());
+        settings.resolve_account_defaults();
+        assert_eq!(
+            settings.accounts["alice"].software.as_deref(),
+            Some(concat!("MultiDuino ", env!("CARGO_PKG_VERSION")))
+        );
+        assert_eq!(
+            settings.accounts["bob"].software.as_deref(),
+            Some("Bob's miner")
+        );
+    }
+
+    #[test]
+    fn diff_of_the_same_config_is_empty() {
+        let old = settings(&[("alice", 2, 100), ("bob", 1, 100)]);
+        assert!(old.diff(&old.clone()).is_empty());
+    }
+
+    #[test]
+    fn diff_finds_added_and_removed_accounts() {
+        let old = settings(&[("alice", 2, 100), ("bob", 1, 100)]);
+        let new = settings(&[("bob", 1, 100), ("carol", 3, 100), ("dave", 1, 100)]);
+        assert_eq!(
+            old.diff(&new),
+            ConfigDiff {
+                added: vec!["carol".to_string(), "dave".to_string()],
+                removed: vec!["alice".to_string()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn diff_resizes_accounts_that_only_changed_their_miners() {
+        let old = settings(&[("alice", 2, 100), ("bob", 3, 100)]);
+        let new = settings(&[("alice", 4, 100), ("bob", 1, 100)]);
+        assert_eq!(
+            old.diff(&new),
+            ConfigDiff {
+                resized: vec![("alice".to_string(), 2, 4), ("bob".to_string(), 3, 1)],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn diff_restarts_accounts_with_other_changes() {
+        let old = settings(&[("alice", 3, 100), ("bob", 1, 100), ("carol", 1, 100)]);
+        let mut new = settings(&[("alice", 1, 200), ("bob", 1, 100), ("carol", 1, 100)]);
+        new.accounts.get_mut("carol").unwrap().rig_name = Some("Other".to_string());
+        assert_eq!(
+            old.diff(&new),
+            ConfigDiff {
+                changed: vec!["alice".to_string(), "carol".to_string()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn pool_settings_restart_every_kept_account() {
+        let old = settings(&[("alice", 2, 100), ("bob", 1, 100)]);
+        let mut new = settings(&[("alice", 3, 100), ("carol", 1, 100)]);
+        new.pools = vec!["127.0.0.1:2812".to_string()];
+        assert_eq!(
+            old.diff(&new),
+            ConfigDiff {
+                added: vec!["carol".to_string()],
+                removed: vec!["bob".to_string()],
+                changed: vec!["alice".to_string()],
+                ..Default::default()
+            }
+        );
+
+        // Settings only the dashboard uses don't touch the miners
+        let mut new = old.clone();
+        new.update_interval = 120;
+        assert!(old.diff(&new).is_empty());
+    }
+
+    fn error_keys(errors: Vec<ConfigError>) -> Vec<String> {
+        errors
+            .into_iter()
+            .map(|error| match error {
+                ConfigError::Invalid { key, .. } => key,
+                ConfigError::Load(message) => panic!("{}", message),
+            })
+            .collect()
+    }
+
+    fn load(name: &str, toml: &str) -> Result<DuinoConfig, Vec<ConfigError>> {
+        let path =
+            std::env::temp_dir().join(format!("multi-duino-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, toml).unwrap();
+        let settings = DuinoConfig::load(path.to_str().unwrap());
+        let _ = std::fs::remove_file(&path);
+        settings
+    }
+
+    #[test]
+    fn load_rejects_integers_the_fields_cant_hold() {
+        let errors = load(
+            "range",
+            "update_interval = 60
+            pool_probes = 257
+            [accounts.alice]
+            key = \"secret\"
+            hashrate = 70000
+            miners = 300
+            [accounts.bob]
+            key = \"secret\"
+            hashrate = 65535
+            miners = 255",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "pool_probes: must be at most 255",
+                "accounts.alice.miners: must be at most 255",
+                "accounts.alice.hashrate: must be at most 65535",
+            ]
+        );
+
+        let settings = load(
+            "max",
+            "update_interval = 60
+            [accounts.bob]
+            key = \"secret\"
+            hashrate = 65535
+            miners = 255",
+        )
+        .unwrap();
+        assert_eq!(settings.accounts["bob"].miners, 255);
+        assert_eq!(settings.accounts["bob"].hashrate, 65535);
+    }
+
+    type Edit = fn(&mut DuinoConfig);
+
+    #[test]
+    fn validate_names_the_key_at_fault() {
+        assert!(settings(&[("alice", 1, 100)]).validate().is_empty());
+
+        let cases: [(Edit, &[&str]); 12] = [
+            (|s| s.update_interval = 5, &["update_interval"]),
+            (|s| s.update_interval = 3601, &["update_interval"]),
+            (
+                |s| s.pools = vec!["127.0.0.1:2812".to_string(), "nope".to_string()],
+                &["pools[1]"],
+            ),
+            (
+                |s| s.accounts.get_mut("alice").unwrap().key = " ".to_string(),
+                &["accounts.alice.key"],
+            ),
+            (
+                |s| {
+                    s.accounts.insert("Alice".to_string(), account(1, 100));
+                },
+                &["accounts.alice"],
+            ),
+            (|s| s.software = "Miner,1.0".to_string(), &["software"]),
+            (
+                |s| s.accounts.get_mut("alice").unwrap().software = Some("a,b".to_string()),
+                &["accounts.alice.software"],
+            ),
+            (
+                |s| {
+                    let alice = s.accounts.get_mut("alice").unwrap();
+                    alice.job_type = account::JobType::Esp32;
+                    alice.difficulty = Some(account::DifficultyTier::Medium);
+                },
+                &["accounts.alice.difficulty"],
+            ),
+            (|s| s.job_timeout = 0, &["job_timeout"]),
+            (|s| s.feedback_timeout = 601, &["feedback_timeout"]),
+            (
+                |s| s.accounts.get_mut("alice").unwrap().job_timeout = Some(0),
+                &["accounts.alice.job_timeout"],
+            ),
+            (
+                |s| {
+                    let alice = s.accounts.get_mut("alice").unwrap();
+                    alice.miners = 0;
+                    alice.feedback_timeout = Some(601);
+                },
+                &["accounts.alice.miners", "accounts.alice.feedback_timeout"],
+            ),
+        ];
+        for (index, (break_setting, keys)) in cases.iter().enumerate() {
+            let mut settings = settings(&[("alice", 1, 100)]);
+            break_setting(&mut settings);
+            assert_eq!(error_keys(settings.validate()), *keys, "case {}", index);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+// Number of recent round trips the figures are taken over
+const SAMPLES: usize = 100;
+
+// Rolling window of share round trips (submit to feedback)
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct LatencyStats {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    // All samples of several windows, e.g. every miner of an account
+    pub fn combine<'a>(stats: impl Iterator<Item = &'a LatencyStats>) -> LatencyStats {
+        LatencyStats {
+            samples: stats
+                .flat_map(|stats| stats.samples.iter().copied())
+                .collect(),
+        }
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+
+    // Nearest-rank percentile
+    pub fn p95(&self) -> Option<Duration> {
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort();
+        let rank = (sorted.len() as f64 * 0.95).ceil() as usize;
+        sorted.get(rank.checked_sub(1)?).copied()
+    }
+}
+
+// min/avg/p95 in milliseconds
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min(), self.avg(), self.p95()) {
+            (Some(min), Some(avg), Some(p95)) => write!(
+                f,
+                "{:.1}/{:.1}/{:.1} ms",
+                min.as_secs_f64() * 1000.0,
+                avg.as_secs_f64() * 1000.0,
+                p95.as_secs_f64() * 1000.0
+            ),
+            _ => write!(f, "-"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn stats(samples: impl IntoIterator<Item = u64>) -> LatencyStats {
+        let mut stats = LatencyStats::default();
+        for sample in samples {
+            stats.record(ms(sample));
+        }
+        stats
+    }
+
+    #[test]
+    fn empty_window_has_no_figures() {
+        let stats = LatencyStats::default();
+        assert_eq!((stats.min(), stats.avg(), stats.p95()), (None, None, None));
+        assert_eq!(stats.to_string(), "-");
+    }
+
+    #[test]
+    fn window_keeps_the_latest_samples() {
+        let stats = stats(1..=150);
+        assert_eq!(stats.samples.len(), SAMPLES);
+        assert_eq!(stats.samples.front(), Some(&ms(51)));
+        assert_eq!(stats.samples.back(), Some(&ms(150)));
+        assert_eq!(stats.min(), Some(ms(51)));
+    }
+
+    #[test]
+    fn min_and_avg() {
+        let stats = stats([30, 10, 20]);
+        assert_eq!(stats.min(), Some(ms(10)));
+        assert_eq!(stats.avg(), Some(ms(20)));
+        assert_eq!(stats.to_string(), "10.0/20.0/30.0 ms");
+    }
+
+    #[test]
+    fn p95_is_nearest_rank() {
+        assert_eq!(stats([7]).p95(), Some(ms(7)));
+        // Rank ceil(0.95 * 20) = 19
+        assert_eq!(stats((1..=20).rev()).p95(), Some(ms(19)));
+        // Only the last 100 of 120 samples count, rank 95 of 21..=120
+        assert_eq!(stats(1..=120).p95(), Some(ms(115)));
+    }
+
+    #[test]
+    fn combine_takes_every_sample() {
+        let combined = LatencyStats::combine([stats(1..=100), stats(101..=200)].iter());
+        assert_eq!(combined.samples.len(), 200);
+        assert_eq!(combined.min(), Some(ms(1)));
+        assert_eq!(combined.avg(), Some(Duration::from_micros(100_500)));
+        assert_eq!(combined.p95(), Some(ms(190)));
+        assert!(LatencyStats::combine(std::iter::empty()).avg().is_none());
+    }
+}
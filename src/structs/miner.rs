@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use super::latency::LatencyStats;
+use crate::protocol::{ServerVersion, ShareFeedback};
+
+// Live state of a single mining task, keyed by its miner id
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct MinerStats {
+    pub account: String,
+    // Difficulty of the last job
+    pub difficulty: u32,
+    pub accepted: u32,
+    pub blocks: u32,
+    // Jobs given up on because no nonce in their range matched
+    pub unsolved: u32,
+    pub rejections: HashMap<String, u32>,
+    pub reconnects: u32,
+    // Hashrate reported with the last share
+    pub hashrate: f64,
+    pub last_share: Option<DateTime<Local>>,
+    pub share_latency: LatencyStats,
+    // Banner of the pool the miner is connected to, None if it couldn't be parsed
+    pub server_version: Option<ServerVersion>,
+    // Set while the miner reconnects after the pool stopped answering
+    pub stalled: bool,
+    pub stalls: u32,
+}
+
+impl MinerStats {
+    pub fn record_share(&mut self, feedback: &ShareFeedback, hashrate: f64, round_trip: Duration) {
+        match feedback {
+            ShareFeedback::Good => self.accepted += 1,
+            ShareFeedback::Block => {
+                self.accepted += 1;
+                self.blocks += 1;
+            }
+            ShareFeedback::Bad(reason) => *self.rejections.entry(reason.clone()).or_insert(0) += 1,
+        }
+        self.hashrate = hashrate;
+        self.last_share = Some(Local::now());
+        self.share_latency.record(round_trip);
+    }
+
+    pub fn rejected(&self) -> u32 {
+        self.rejections.values().sum()
+    }
+}
+
+// Running mining task, sending true on stop makes it finish its share and exit
+pub struct MinerTask {
+    pub stop: watch::Sender<bool>,
+    pub handle: JoinHandle<()>,
+}
@@ -1,6 +1,10 @@
+use std::time::Duration;
+
 use serde_derive::Serialize;
 use serde_derive::Deserialize;
 
+use super::latency::LatencyStats;
+
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct PoolResult {
     pub client: String,
@@ -10,4 +14,56 @@ pub struct PoolResult {
     pub region: String,
     pub server: String,
     pub success: bool
-}
\ No newline at end of file
+}
+
+impl PoolResult {
+    // Static pool entry from a host:port string
+    pub fn from_address(address: &str) -> Option<PoolResult> {
+        let (ip, port) = address.rsplit_once(':')?;
+        if ip.is_empty() {
+            return None;
+        }
+        Some(PoolResult {
+            client: String::new(),
+            ip: ip.to_string(),
+            name: address.to_string(),
+            port: port.parse().ok()?,
+            region: String::new(),
+            server: "static".to_string(),
+            success: true,
+        })
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+}
+
+// Pools a miner works through in order, the getPool answer (if enabled) comes first
+#[derive(Default, Debug, Clone)]
+pub struct PoolList {
+    pub api_url: Option<String>,
+    pub pools: Vec<PoolResult>,
+}
+
+// Measured latencies of a pool, None when that step failed, timed out or the pool wasn't probed
+#[derive(Default, Debug, Clone)]
+pub struct PoolLatency {
+    pub pool: PoolResult,
+    pub probed: bool,
+    pub connect: Option<Duration>,
+    pub job: Option<Duration>,
+    // Share round trips of every miner that mined on this pool
+    pub shares: LatencyStats,
+}
+
+impl PoolLatency {
+    // A pool is healthy once it handed out a job
+    pub fn is_healthy(&self) -> bool {
+        self.job.is_some()
+    }
+
+    pub fn total(&self) -> Option<Duration> {
+        Some(self.connect? + self.job?)
+    }
+}
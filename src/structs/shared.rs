@@ -0,0 +1,22 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+
+use super::account::AccountData;
+use super::miner::{MinerStats, MinerTask};
+use super::pool::PoolLatency;
+
+// State shared by the miners, the config watcher and the dashboard, clones share the same maps
+#[derive(Default, Clone)]
+pub struct SharedState {
+    // Dashboard account data by account name, listed in account_list order
+    pub accounts: Arc<RwLock<HashMap<String, AccountData>>>,
+    pub account_list: Arc<RwLock<Vec<String>>>,
+    // Stats by miner id, kept across restarts of a miner
+    pub miners: Arc<RwLock<HashMap<String, MinerStats>>>,
+    // Latest probe results and share round trips by pool address, shown on the Pools tab
+    pub latencies: Arc<RwLock<HashMap<String, PoolLatency>>>,
+    // Running mining tasks by miner id
+    pub tasks: Arc<RwLock<HashMap<String, MinerTask>>>,
+}
@@ -7,6 +7,7 @@ pub enum Event<I> {
 pub enum MenuItem {
     Dashboard,
     Logs,
+    Pools,
 }
 
 impl From<MenuItem> for usize {
@@ -14,6 +15,7 @@ impl From<MenuItem> for usize {
         match input {
             MenuItem::Dashboard => 0,
             MenuItem::Logs => 1,
+            MenuItem::Pools => 2,
         }
     }
 }
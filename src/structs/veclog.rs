@@ -1,8 +1,14 @@
 use std::{
     collections::VecDeque,
-    sync::{Arc, Mutex},
+    fs::File,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use chrono::Local;
 use log::{Metadata, Record};
 
 static MAX_LOGS: usize = 1000;
@@ -10,18 +16,52 @@ static MAX_LOGS: usize = 1000;
 #[derive(Default)]
 pub struct VecLogger {
     logs: Arc<Mutex<VecDeque<String>>>,
+    echo: AtomicBool,
+    file: Mutex<Option<File>>,
+    secrets: Mutex<Vec<String>>,
 }
 
 impl VecLogger {
+    // Also print every log line to stdout, used when running without the TUI
+    pub fn set_echo(&self, echo: bool) {
+        self.echo.store(echo, Ordering::Relaxed);
+    }
+
+    // Also append every log line to a file
+    pub fn set_file(&self, file: File) {
+        *self.file.lock().unwrap() = Some(file);
+    }
+
+    // Masks these values in every line before it is stored, printed or written
+    pub fn add_secrets(&self, secrets: impl IntoIterator<Item = String>) {
+        let mut known = self.secrets.lock().unwrap();
+        for secret in secrets {
+            if !secret.is_empty() && !known.contains(&secret) {
+                known.push(secret);
+            }
+        }
+        // Longest first so a secret containing another one is masked as a whole
+        known.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    }
+
+    fn redact(&self, line: String) -> String {
+        self.secrets
+            .lock()
+            .unwrap()
+            .iter()
+            .fold(line, |line, secret| line.replace(secret, &mask(secret)))
+    }
+
     pub fn logs(&self) -> VecDeque<String> {
         self.logs.lock().unwrap().clone()
     }
 
     pub fn log(&self, message: String) {
+        let message = self.redact(message);
         let mut logs = self.logs.lock().unwrap();
         logs.push_back(message);
-        while self.logs().len() > MAX_LOGS {
-            logs.pop_back();
+        while logs.len() > MAX_LOGS {
+            logs.pop_front();
         }
     }
 }
@@ -33,10 +73,19 @@ impl log::Log for VecLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            self.logs
-                .lock()
-                .unwrap()
-                .push_back(format!("{}", record.args()));
+            let line = self.redact(format!("{}", record.args()));
+            if self.echo.load(Ordering::Relaxed) {
+                let _ = writeln!(io::stdout(), "{}", line);
+            }
+            if let Some(file) = self.file.lock().unwrap().as_mut() {
+                let _ = writeln!(
+                    file,
+                    "[{}] {}",
+                    Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    line
+                );
+            }
+            self.logs.lock().unwrap().push_back(line);
             while self.logs().len() > MAX_LOGS {
                 self.logs.lock().unwrap().pop_front();
             }
@@ -45,3 +94,54 @@ impl log::Log for VecLogger {
 
     fn flush(&self) {}
 }
+
+// Keeps the first two characters of longer secrets so keys can still be told apart
+pub fn mask(secret: &str) -> String {
+    if secret.chars().count() < 8 {
+        return "****".to_string();
+    }
+    format!("{}****", secret.chars().take(2).collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Log;
+
+    use super::*;
+
+    fn record(logger: &VecLogger, args: std::fmt::Arguments) {
+        Log::log(logger, &Record::builder().args(args).build());
+    }
+
+    #[test]
+    fn configured_keys_never_reach_the_logs() {
+        let key = "supersecretkey";
+        let path =
+            std::env::temp_dir().join(format!("multi-duino-veclog-{}.log", std::process::id()));
+        let logger = VecLogger::default();
+        logger.add_secrets(["short".to_string(), key.to_string()]);
+        logger.set_file(File::create(&path).unwrap());
+
+        record(
+            &logger,
+            format_args!(
+                "WARNING: Account {} either doesn't exist or has invalid mining key: {}",
+                "youraccount", key
+            ),
+        );
+        record(&logger, format_args!("JOB,youraccount,LOW,{},short", key));
+        logger.log(format!("Dashboard message with {}", key));
+
+        let file = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let logs = logger.logs();
+        assert_eq!(logs.len(), 3);
+        assert_eq!(file.lines().count(), 2);
+        for line in logs.iter().map(String::as_str).chain(file.lines()) {
+            assert!(!line.contains(key), "{:?}", line);
+            assert!(!line.contains("short"), "{:?}", line);
+        }
+        assert!(logs[0].ends_with("has invalid mining key: su****"));
+        assert_eq!(logs[1], "JOB,youraccount,LOW,su****,****");
+    }
+}
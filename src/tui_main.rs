@@ -4,14 +4,12 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
 };
-use futures::future::FutureExt;
 use std::{
     collections::HashMap,
     io,
-    sync::Arc,
     time::{Duration, Instant},
 };
-use tokio::sync::{mpsc::Sender, RwLock};
+use tokio::sync::mpsc::Sender;
 use tui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
@@ -25,13 +23,10 @@ use tui::{
 
 use crossterm::event::{Event as CEvent, KeyCode};
 
-use crate::structs::{self};
 use crate::LOGGER;
+use multi_duino::structs::{self};
 
-pub async fn init(
-    tui_accounts: Arc<RwLock<HashMap<String, structs::AccountData>>>,
-    tui_accounts_list: Arc<RwLock<Vec<String>>>,
-) {
+pub async fn init(state: structs::SharedState) {
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend).expect("Couldn't create terminal abstraction!");
@@ -41,13 +36,18 @@ pub async fn init(
     let (tx, mut rx) = tokio::sync::mpsc::channel(100);
     tokio::spawn(tick(tx.clone()));
 
-    let menu_titles = vec!["Dashboard", "Logs", "Quit"];
+    let menu_titles = ["Dashboard", "Logs", "Pools", "Quit"];
     let mut active_menu_item = structs::MenuItem::Dashboard;
     let mut account_list_state = ListState::default();
     account_list_state.select(Some(0));
 
     tokio::task::spawn(async move {
         loop {
+            // Drawn under the read locks rather than from copies of the maps
+            let miners = state.miners.read().await;
+            let latencies = state.latencies.read().await;
+            let accounts = state.accounts.read().await;
+            let account_list = state.account_list.read().await;
             terminal
                 .draw(|rect| {
                     let size = rect.size();
@@ -83,10 +83,10 @@ pub async fn init(
                     rect.render_widget(tabs, chunks[0]);
                     match active_menu_item {
                         structs::MenuItem::Logs => rect.render_widget(render_logs(), chunks[1]),
+                        structs::MenuItem::Pools => {
+                            rect.render_widget(render_pools(&latencies), chunks[1])
+                        }
                         structs::MenuItem::Dashboard => {
-                            let accounts = tui_accounts.read().now_or_never().unwrap();
-                            let account_list = tui_accounts_list.read().now_or_never().unwrap();
-
                             let pets_chunks = Layout::default()
                                 .direction(Direction::Horizontal)
                                 .constraints(
@@ -94,18 +94,31 @@ pub async fn init(
                                         .as_ref(),
                                 )
                                 .split(chunks[1]);
-                            let (left, right) =
-                                render_dashboard(&account_list_state, &account_list, &accounts);
+                            let detail_chunks = Layout::default()
+                                .direction(Direction::Vertical)
+                                .constraints(
+                                    [Constraint::Min(18), Constraint::Percentage(30)].as_ref(),
+                                )
+                                .split(pets_chunks[1]);
+                            let (left, right, stalled) = render_dashboard(
+                                &account_list_state,
+                                &account_list,
+                                &accounts,
+                                &miners,
+                            );
                             rect.render_stateful_widget(
                                 left,
                                 pets_chunks[0],
                                 &mut account_list_state,
                             );
-                            rect.render_widget(right, pets_chunks[1]);
+                            rect.render_widget(right, detail_chunks[0]);
+                            rect.render_widget(stalled, detail_chunks[1]);
                         }
                     }
                 })
                 .expect("TODO");
+            // Released before waiting for the next event, which may lock the accounts again
+            drop((miners, latencies, accounts, account_list));
 
             match rx.recv().await.expect("TODO") {
                 structs::Event::Input(event) => match event.code {
@@ -113,7 +126,7 @@ pub async fn init(
                         execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0)).expect("TODO");
                         disable_raw_mode().expect("TODO");
                         terminal.show_cursor().expect("TODO");
-                        std::process::exit(1);
+                        break;
                     }
                     KeyCode::Left | KeyCode::Char('d') => {
                         active_menu_item = structs::MenuItem::Dashboard
@@ -121,10 +134,11 @@ pub async fn init(
                     KeyCode::Right | KeyCode::Char('l') => {
                         active_menu_item = structs::MenuItem::Logs
                     }
+                    KeyCode::Char('p') => active_menu_item = structs::MenuItem::Pools,
                     KeyCode::Down => match active_menu_item {
                         structs::MenuItem::Dashboard => {
                             if let Some(selected) = account_list_state.selected() {
-                                let accounts = tui_accounts.read().await;
+                                let accounts = state.accounts.read().await;
                                 if selected >= accounts.len() - 1 {
                                     account_list_state.select(Some(0));
                                 } else {
@@ -132,7 +146,7 @@ pub async fn init(
                                 }
                             }
                         }
-                        structs::MenuItem::Logs => {}
+                        structs::MenuItem::Logs | structs::MenuItem::Pools => {}
                     },
                     KeyCode::Up => match active_menu_item {
                         structs::MenuItem::Dashboard => {
@@ -140,12 +154,12 @@ pub async fn init(
                                 if selected > 0 {
                                     account_list_state.select(Some(selected - 1));
                                 } else {
-                                    let accounts = tui_accounts.read().await;
+                                    let accounts = state.accounts.read().await;
                                     account_list_state.select(Some(accounts.len() - 1));
                                 }
                             }
                         }
-                        structs::MenuItem::Logs => {}
+                        structs::MenuItem::Logs | structs::MenuItem::Pools => {}
                     },
                     _ => {}
                 },
@@ -196,11 +210,86 @@ pub fn render_logs<'a>() -> Paragraph<'a> {
         )
 }
 
+// Probed pools, fastest healthy ones first
+fn render_pools<'a>(latencies: &HashMap<String, structs::PoolLatency>) -> Table<'a> {
+    let mut pools: Vec<&structs::PoolLatency> = latencies.values().collect();
+    pools.sort_by_key(|pool| (!pool.is_healthy(), pool.total()));
+    let millis = |latency: Option<Duration>| match latency {
+        Some(latency) => format!("{:.1} ms", latency.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    };
+    let rows: Vec<Row> = pools
+        .iter()
+        .map(|pool| {
+            Row::new(vec![
+                Cell::from(Span::raw(pool.pool.address())),
+                Cell::from(Span::raw(format!(
+                    "{}/{}",
+                    pool.pool.server, pool.pool.name
+                ))),
+                Cell::from(Span::raw(if pool.pool.region.is_empty() {
+                    "-".to_string()
+                } else {
+                    pool.pool.region.clone()
+                })),
+                Cell::from(Span::raw(millis(pool.connect))),
+                Cell::from(Span::raw(millis(pool.job))),
+                Cell::from(Span::raw(pool.shares.to_string())),
+                Cell::from(Span::raw(if pool.is_healthy() {
+                    "Healthy"
+                } else if !pool.probed {
+                    "Not probed"
+                } else {
+                    "Unreachable"
+                })),
+            ])
+        })
+        .collect();
+
+    Table::new(rows)
+        .header(Row::new(
+            [
+                "Address",
+                "Pool",
+                "Region",
+                "Connect",
+                "Job",
+                "Share RTT (min/avg/p95)",
+                "Status",
+            ]
+            .iter()
+            .map(|title| {
+                Cell::from(Span::styled(
+                    *title,
+                    Style::default().add_modifier(Modifier::BOLD),
+                ))
+            })
+            .collect::<Vec<Cell>>(),
+        ))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Pools")
+                .border_type(BorderType::Plain),
+        )
+        .widths(&[
+            Constraint::Percentage(17),
+            Constraint::Percentage(20),
+            Constraint::Percentage(10),
+            Constraint::Percentage(10),
+            Constraint::Percentage(10),
+            Constraint::Percentage(20),
+            Constraint::Percentage(13),
+        ])
+}
+
 fn render_dashboard<'a>(
     pet_list_state: &ListState,
     tui_accounts_list: &[String],
     tui_accounts: &HashMap<String, structs::AccountData>,
-) -> (List<'a>, Table<'a>) {
+    tui_miners: &HashMap<String, structs::MinerStats>,
+) -> (List<'a>, Table<'a>, List<'a>) {
     let pets = Block::default()
         .borders(Borders::ALL)
         .style(Style::default().fg(Color::White))
@@ -235,7 +324,14 @@ fn render_dashboard<'a>(
 
     let pet_detail = Table::new(vec![
         Row::new(vec![
-            Cell::from(Span::raw(selected_account.hashrate.to_string())),
+            Cell::from(Span::raw(format!(
+                "{} ({} measured)",
+                selected_account.hashrate,
+                account_miners(&selected_name, tui_miners)
+                    .map(|miner| miner.hashrate)
+                    .sum::<f64>()
+                    .round()
+            ))),
             Cell::from(Span::raw(selected_account.miners.to_string())),
             Cell::from(Span::raw(selected_account.connected.to_string())),
             Cell::from(Span::raw(selected_account.current_balance.to_string())),
@@ -265,6 +361,107 @@ fn render_dashboard<'a>(
             Cell::from(Span::raw(selected_account.staked.to_string())),
             Cell::from(Span::raw(selected_account.warnings.to_string())),
         ]),
+        Row::new(vec![Cell::from(Span::raw(""))]),
+        Row::new(vec![
+            Cell::from(Span::styled(
+                "Tier",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "Difficulty",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "Reconnects",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "Rejections",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+        ]),
+        Row::new(vec![
+            Cell::from(Span::raw(selected_account.tier.to_string())),
+            Cell::from(Span::raw(assigned_difficulty(&selected_name, tui_miners))),
+            Cell::from(Span::raw(
+                account_miners(&selected_name, tui_miners)
+                    .map(|miner| miner.reconnects)
+                    .sum::<u32>()
+                    .to_string(),
+            )),
+            Cell::from(Span::raw(rejection_reasons(&selected_name, tui_miners))),
+        ]),
+        Row::new(vec![Cell::from(Span::raw(""))]),
+        Row::new(vec![
+            Cell::from(Span::styled(
+                "Accepted",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "Rejected",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "Blocks",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "Last Share",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+        ]),
+        Row::new(vec![
+            Cell::from(Span::raw(
+                account_miners(&selected_name, tui_miners)
+                    .map(|miner| miner.accepted)
+                    .sum::<u32>()
+                    .to_string(),
+            )),
+            Cell::from(Span::raw(
+                account_miners(&selected_name, tui_miners)
+                    .map(|miner| miner.rejected())
+                    .sum::<u32>()
+                    .to_string(),
+            )),
+            Cell::from(Span::raw(
+                account_miners(&selected_name, tui_miners)
+                    .map(|miner| miner.blocks)
+                    .sum::<u32>()
+                    .to_string(),
+            )),
+            Cell::from(Span::raw(
+                account_miners(&selected_name, tui_miners)
+                    .filter_map(|miner| miner.last_share)
+                    .max()
+                    .map(|last_share| last_share.format("%H:%M:%S").to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            )),
+        ]),
+        Row::new(vec![Cell::from(Span::raw(""))]),
+        Row::new(vec![
+            Cell::from(Span::styled(
+                "Share RTT",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::styled(
+                "Unsolved Jobs",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+        ]),
+        Row::new(vec![
+            Cell::from(Span::raw(
+                structs::LatencyStats::combine(
+                    account_miners(&selected_name, tui_miners).map(|miner| &miner.share_latency),
+                )
+                .to_string(),
+            )),
+            Cell::from(Span::raw(
+                account_miners(&selected_name, tui_miners)
+                    .map(|miner| miner.unsolved)
+                    .sum::<u32>()
+                    .to_string(),
+            )),
+        ]),
     ])
     .header(Row::new(vec![
         Cell::from(Span::styled(
@@ -292,11 +489,83 @@ fn render_dashboard<'a>(
             .border_type(BorderType::Plain),
     )
     .widths(&[
-        Constraint::Percentage(25),
-        Constraint::Percentage(25),
-        Constraint::Percentage(25),
-        Constraint::Percentage(25),
+        Constraint::Percentage(20),
+        Constraint::Percentage(20),
+        Constraint::Percentage(20),
+        Constraint::Percentage(40),
     ]);
 
-    (list, pet_detail)
+    (list, pet_detail, render_stalled(&selected_name, tui_miners))
+}
+
+// Miners of an account that are reconnecting because their pool stopped answering
+fn render_stalled<'a>(
+    account: &str,
+    tui_miners: &HashMap<String, structs::MinerStats>,
+) -> List<'a> {
+    let mut stalled: Vec<(&String, &structs::MinerStats)> = tui_miners
+        .iter()
+        .filter(|(_, miner)| miner.stalled)
+        .filter(|(_, miner)| account == "Global" || miner.account == account)
+        .collect();
+    stalled.sort_by_key(|(id, _)| *id);
+    let items: Vec<ListItem> = stalled
+        .iter()
+        .map(|(id, miner)| {
+            ListItem::new(Span::styled(
+                format!("{} ({} stalls)", id, miner.stalls),
+                Style::default().fg(Color::Red),
+            ))
+        })
+        .collect();
+    List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title(format!("Stalled miners ({})", stalled.len()))
+            .border_type(BorderType::Plain),
+    )
+}
+
+// Stats of an account's miners, or of every miner for Global
+fn account_miners<'a>(
+    account: &'a str,
+    tui_miners: &'a HashMap<String, structs::MinerStats>,
+) -> impl Iterator<Item = &'a structs::MinerStats> {
+    tui_miners
+        .values()
+        .filter(move |miner| account == "Global" || miner.account == account)
+}
+
+// Range of difficulties the pool assigned to the miners of an account
+fn assigned_difficulty(account: &str, tui_miners: &HashMap<String, structs::MinerStats>) -> String {
+    let difficulties: Vec<u32> = account_miners(account, tui_miners)
+        .map(|miner| miner.difficulty)
+        .filter(|difficulty| *difficulty > 0)
+        .collect();
+    match (difficulties.iter().min(), difficulties.iter().max()) {
+        (Some(min), Some(max)) if min == max => min.to_string(),
+        (Some(min), Some(max)) => format!("{}-{}", min, max),
+        _ => "-".to_string(),
+    }
+}
+
+// Rejection reasons of an account's miners, most frequent first
+fn rejection_reasons(account: &str, tui_miners: &HashMap<String, structs::MinerStats>) -> String {
+    let mut reasons: HashMap<&str, u32> = HashMap::new();
+    for miner in account_miners(account, tui_miners) {
+        for (reason, count) in &miner.rejections {
+            *reasons.entry(reason).or_insert(0) += count;
+        }
+    }
+    if reasons.is_empty() {
+        return "-".to_string();
+    }
+    let mut reasons: Vec<(&str, u32)> = reasons.into_iter().collect();
+    reasons.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    reasons
+        .iter()
+        .map(|(reason, count)| format!("{} ({})", reason, count))
+        .collect::<Vec<String>>()
+        .join(", ")
 }
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use multi_duino::api;
+use multi_duino::mock::rest;
+use multi_duino::structs::{Account, AccountData, DuinoConfig, SharedState};
+use tokio::net::TcpListener;
+
+// Config pointing at a mock REST server that serves the repo's fixtures
+async fn offline_settings(accounts: &[(&str, u8, u16)]) -> DuinoConfig {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    tokio::spawn(rest::run(listener, fixtures));
+
+    let mut settings = DuinoConfig {
+        update_interval: 10,
+        api_url: format!("http://{}", address),
+        ..Default::default()
+    };
+    for (name, miners, hashrate) in accounts {
+        settings.accounts.insert(
+            name.to_string(),
+            Account {
+                key: "secret".to_string(),
+                miners: *miners,
+                hashrate: *hashrate,
+                ..Default::default()
+            },
+        );
+    }
+    settings
+}
+
+async fn refresh(settings: &DuinoConfig) -> (HashMap<String, AccountData>, Vec<String>) {
+    let state = SharedState::default();
+    api::refresh_accounts(&state, settings).await;
+    let accounts = state.accounts.read().await.clone();
+    let account_list = state.account_list.read().await.clone();
+    (accounts, account_list)
+}
+
+#[tokio::test]
+async fn refresh_builds_account_data_from_the_api() {
+    let settings = offline_settings(&[("youraccount", 25, 340), ("other", 2, 100)]).await;
+    let (accounts, account_list) = refresh(&settings).await;
+
+    let mut names = account_list.clone();
+    names.sort();
+    assert_eq!(names, ["Global", "other", "youraccount"]);
+    assert_eq!(account_list.last().unwrap(), "Global");
+
+    let account = &accounts["youraccount"];
+    assert_eq!(account.status, "Connected");
+    assert_eq!(account.hashrate, 25 * 340);
+    assert_eq!(account.miners, 25);
+    assert_eq!(account.connected, 0);
+    assert_eq!(account.current_balance, 125.5);
+    assert_eq!(account.staked, 50.0);
+    assert_eq!(account.warnings, 0);
+    assert_eq!(account.tier, "LOW");
+    // Valued at the highest price the API reports
+    assert!((account.estimated_balance - 125.5 * 0.00025).abs() < 1e-9);
+
+    let global = &accounts["Global"];
+    assert_eq!(global.hashrate, 25 * 340 + 2 * 100);
+    assert_eq!(global.miners, 27);
+    assert_eq!(global.current_balance, 251.0);
+}
+
+#[tokio::test]
+async fn banned_account_is_not_found() {
+    let settings = offline_settings(&[("youraccount", 1, 100), ("banned", 4, 100)]).await;
+    let (accounts, account_list) = refresh(&settings).await;
+
+    assert!(account_list.contains(&"banned".to_string()));
+    let banned = &accounts["banned"];
+    assert_eq!(banned.status, "Not found");
+    assert_eq!(banned.hashrate, 0);
+    assert_eq!(banned.miners, 0);
+    assert_eq!(banned.current_balance, 0.0);
+
+    // Only the verified account counts towards the totals
+    let global = &accounts["Global"];
+    assert_eq!(global.miners, 1);
+    assert_eq!(global.hashrate, 100);
+    assert_eq!(global.current_balance, 125.5);
+}
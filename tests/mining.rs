@@ -0,0 +1,217 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use multi_duino::miner;
+use multi_duino::mock::pool::{self, Script};
+use multi_duino::structs::{
+    Account, Algorithm, MinerStats, MiningMode, PoolList, PoolResult, SharedState,
+};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+static MINER_ID: &str = "tester/001";
+static DEADLINE: Duration = Duration::from_secs(30);
+
+// One miner mining against a mock pool on an ephemeral port
+struct Session {
+    state: SharedState,
+    pool: PoolResult,
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl Session {
+    async fn start(script: Script, algorithm: Algorithm) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(pool::run(listener, Arc::new(script)));
+
+        let pool = PoolResult::from_address(&address).unwrap();
+        let account = Account {
+            key: "secret".to_string(),
+            hashrate: 1000,
+            miners: 1,
+            mode: MiningMode::Cpu,
+            algorithm,
+            rig_name: Some("tests".to_string()),
+            software: Some("MultiDuino tests".to_string()),
+            job_timeout: Some(1),
+            feedback_timeout: Some(1),
+            ..Default::default()
+        };
+        let state = SharedState::default();
+        let (stop, stop_rx) = watch::channel(false);
+        let handle = tokio::spawn(miner::mine(
+            pool.clone(),
+            MINER_ID.to_string(),
+            "tester".to_string(),
+            account,
+            state.clone(),
+            PoolList {
+                api_url: None,
+                pools: vec![pool.clone()],
+            },
+            stop_rx,
+        ));
+        Session {
+            state,
+            pool,
+            stop,
+            handle,
+        }
+    }
+
+    // Polls the miner's stats until the condition holds, panics after DEADLINE
+    async fn wait_for(&self, condition: impl Fn(&MinerStats) -> bool) -> MinerStats {
+        tokio::time::timeout(DEADLINE, async {
+            loop {
+                if let Some(miner) = self.state.miners.read().await.get(MINER_ID) {
+                    if condition(miner) {
+                        return miner.clone();
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| {
+            panic!(
+                "{:?}",
+                self.state.miners.try_read().map(|stats| stats.clone())
+            )
+        })
+    }
+
+    async fn stop(self) -> MinerStats {
+        self.stop.send_replace(true);
+        tokio::time::timeout(DEADLINE, self.handle)
+            .await
+            .expect("Miner didn't stop")
+            .unwrap();
+        self.state.miners.read().await[MINER_ID].clone()
+    }
+}
+
+fn script(difficulty: u32) -> Script {
+    Script {
+        difficulty,
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn shares_get_good_bad_and_block_answers() {
+    let session = Session::start(
+        Script {
+            bad_every: 3,
+            block_every: 2,
+            ..script(10)
+        },
+        Algorithm::DucoS1,
+    )
+    .await;
+    session.wait_for(|miner| miner.accepted >= 4).await;
+    let latencies = session.state.latencies.read().await.clone();
+    let address = session.pool.address();
+    let miner = session.stop().await;
+
+    // Shares 1 good, 2 block, 3 bad, 4 block, 5 good, 6 bad, ...
+    assert!(miner.blocks >= 2, "{:?}", miner);
+    assert!(miner.rejections["Injected rejection"] >= 1, "{:?}", miner);
+    assert_eq!(miner.rejections.len(), 1, "{:?}", miner);
+    assert_eq!(miner.reconnects, 0);
+    assert_eq!(miner.unsolved, 0);
+    assert_eq!(miner.difficulty, 10);
+    assert!(miner
+        .server_version
+        .is_some_and(|version| version.is_supported()));
+    assert!(miner.share_latency.avg().is_some());
+    assert!(latencies[&address].shares.avg().is_some());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn xxhash_shares_are_accepted() {
+    let session = Session::start(script(10), Algorithm::Xxhash).await;
+    session.wait_for(|miner| miner.accepted >= 3).await;
+    let miner = session.stop().await;
+    assert_eq!(miner.rejected(), 0, "{:?}", miner);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn dropped_connection_is_reconnected() {
+    let session = Session::start(
+        Script {
+            drop_after: 2,
+            ..script(10)
+        },
+        Algorithm::DucoS1,
+    )
+    .await;
+    let miner = session
+        .wait_for(|miner| miner.reconnects >= 1 && miner.accepted >= 3)
+        .await;
+    assert_eq!(miner.stalls, 0);
+    let miner = session.stop().await;
+    assert_eq!(miner.rejected(), 0, "{:?}", miner);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn stalled_pool_is_detected_and_left() {
+    let session = Session::start(
+        Script {
+            stall_after: 2,
+            ..script(10)
+        },
+        Algorithm::DucoS1,
+    )
+    .await;
+    session
+        .wait_for(|miner| miner.stalled && miner.stalls == 1)
+        .await;
+    let miner = session
+        .wait_for(|miner| !miner.stalled && miner.accepted >= 3)
+        .await;
+    assert!(miner.reconnects >= 1);
+    session.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn unsolvable_jobs_are_skipped() {
+    let session = Session::start(
+        Script {
+            unsolvable_every: 2,
+            ..script(10)
+        },
+        Algorithm::DucoS1,
+    )
+    .await;
+    let miner = session
+        .wait_for(|miner| miner.unsolved >= 1 && miner.accepted >= 2)
+        .await;
+    assert_eq!(miner.rejected(), 0, "{:?}", miner);
+    session.stop().await;
+}
+
+#[test]
+fn stopping_cancels_cpu_hashing() {
+    let (done, finished) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            // Up to a billion nonces, far more than the test waits for
+            let session = Session::start(script(10_000_000), Algorithm::DucoS1).await;
+            session
+                .wait_for(|miner| miner.difficulty == 10_000_000)
+                .await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            session.stop().await;
+        });
+        // Dropping the runtime waits for its blocking hashing workers
+        drop(runtime);
+        done.send(()).unwrap();
+    });
+    finished
+        .recv_timeout(Duration::from_secs(10))
+        .expect("Hashing worker kept running after the miner stopped");
+}